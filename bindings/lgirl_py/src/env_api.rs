@@ -1,37 +1,262 @@
 use pyo3::exceptions;
 use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+use pyo3::types::{PyBytes, PyDict};
 
 // Import your crate by its new name
 use rgirl;
-use rgirl::device::Device;
+use rgirl::device::{Device, StdoutPrinter, TestRomResult};
+use rgirl::error;
+use rgirl::{
+    FieldEncoding, FrameFormat, GbMode, MirrorField, MirrorSpec, RewardConfig, DEFAULT_WRAM_SEED,
+    SCREEN_H, SCREEN_W,
+};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
 
-/// A tiny helper to expose mirror size constant to Python (change if you have a MIRROR_SIZE export)
+pyo3::create_exception!(rgirl_env, RomLoadError, exceptions::PyRuntimeError);
+pyo3::create_exception!(rgirl_env, UnsupportedMbcError, RomLoadError);
+pyo3::create_exception!(rgirl_env, BadChecksumError, RomLoadError);
+pyo3::create_exception!(rgirl_env, RomTooSmallError, RomLoadError);
+pyo3::create_exception!(rgirl_env, RomSizeMismatchError, RomLoadError);
+pyo3::create_exception!(rgirl_env, ClassicModeError, RomLoadError);
+pyo3::create_exception!(rgirl_env, SaveFileError, RomLoadError);
+
+/// Maps a `Device` construction error to the matching Python exception.
+/// Every class derives from `RomLoadError`, itself a `RuntimeError`.
+fn load_error(e: &str) -> PyErr {
+    let msg = format!("Failed to create Device: {}", e);
+    match e {
+        error::UNSUPPORTED_MBC => UnsupportedMbcError::new_err(msg),
+        error::BAD_CHECKSUM => BadChecksumError::new_err(msg),
+        error::ROM_TOO_SMALL => RomTooSmallError::new_err(msg),
+        error::ROM_SIZE_MISMATCH => RomSizeMismatchError::new_err(msg),
+        error::CLASSIC_MODE_UNSUPPORTED => ClassicModeError::new_err(msg),
+        error::SAVE_READ_FAILED
+        | error::SAVE_LOAD_FAILED
+        | error::CLOCK_LOAD_FAILED
+        | error::RAM_WRONG_LENGTH
+        | error::RAM_TOO_SMALL
+        | error::CLOCK_WRONG_SIZE
+        | error::NO_CLOCK
+        | error::BATTERY_RAM_SIZE_MISMATCH => SaveFileError::new_err(msg),
+        _ => RomLoadError::new_err(msg),
+    }
+}
+
+/// Reject memory accesses running past 0xFFFF.
+fn check_range(addr: u16, length: usize) -> PyResult<()> {
+    if addr as usize + length > 0x10000 {
+        return Err(exceptions::PyValueError::new_err(
+            "Memory range goes past 0xFFFF",
+        ));
+    }
+    Ok(())
+}
+
+/// Runs `f`, turning a Rust panic into a `RuntimeError` instead of letting it unwind into
+/// Python. The emulator may be left in an inconsistent state afterwards.
+fn guard<T>(f: impl FnOnce() -> PyResult<T>) -> PyResult<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "unknown panic".to_string()
+            };
+            Err(exceptions::PyRuntimeError::new_err(format!(
+                "Emulator panicked: {}",
+                msg
+            )))
+        }
+    }
+}
+
+/// Serial callback that keeps every transferred byte for `Env.serial_log()`.
+struct SerialLog(Arc<Mutex<Vec<u8>>>);
+
+impl rgirl::SerialCallback for SerialLog {
+    fn call(&mut self, v: u8) -> Option<u8> {
+        self.0.lock().unwrap().push(v);
+        None
+    }
+}
+
+/// mirror_size() -> int
+/// Size of the default mirror, including the hidden/debug region.
 #[pyfunction]
 fn mirror_size() -> usize {
-    // If your mmu exports MIRROR_SIZE: return it. Otherwise return fallback 0x68 (104)
-    rgirl::mmu::MIRROR_SIZE
+    rgirl::MIRROR_SIZE
+}
+
+/// decode_bcd(data: bytes) -> int
+/// Decodes packed BCD, most significant byte first.
+#[pyfunction]
+fn decode_bcd(data: &[u8]) -> u32 {
+    Device::decode_bcd(data)
+}
+
+/// clock_hz() -> int
+/// CPU cycles per second in single speed mode.
+#[pyfunction]
+fn clock_hz() -> u32 {
+    rgirl::CLOCK_HZ
+}
+
+/// requires_cgb(rom_path: str) -> bool
+/// Whether the ROM only runs on the CGB, so it has to be opened with classic_mode=True.
+#[pyfunction]
+fn requires_cgb(rom_path: String) -> PyResult<bool> {
+    let data = std::fs::read(&rom_path)
+        .map_err(|e| RomLoadError::new_err(format!("Could not read ROM: {}", e)))?;
+    Ok(Device::rom_requires_cgb(&data))
 }
 
 #[pymodule]
 fn rgirl_env(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Env>()?;
+    m.add_class::<VecEnv>()?;
+    m.add_class::<EnvState>()?;
     m.add_function(wrap_pyfunction!(mirror_size, m)?)?;
+    m.add_function(wrap_pyfunction!(requires_cgb, m)?)?;
+    m.add_function(wrap_pyfunction!(clock_hz, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_bcd, m)?)?;
+    m.add("RomLoadError", py.get_type::<RomLoadError>())?;
+    m.add("UnsupportedMbcError", py.get_type::<UnsupportedMbcError>())?;
+    m.add("BadChecksumError", py.get_type::<BadChecksumError>())?;
+    m.add("RomTooSmallError", py.get_type::<RomTooSmallError>())?;
+    m.add(
+        "RomSizeMismatchError",
+        py.get_type::<RomSizeMismatchError>(),
+    )?;
+    m.add("ClassicModeError", py.get_type::<ClassicModeError>())?;
+    m.add("SaveFileError", py.get_type::<SaveFileError>())?;
     Ok(())
 }
 
+/// A saved emulator state from `Env.get_state()`: the bytes of `Env.snapshot()` in a
+/// picklable wrapper, e.g. to send them to another process.
+#[pyclass(module = "rgirl_env")]
+pub struct EnvState {
+    data: Vec<u8>,
+}
+
+#[pymethods]
+impl EnvState {
+    /// __new__(data: bytes)
+    /// Wraps bytes from `Env.snapshot()`; `Env.set_state()` checks them.
+    #[new]
+    fn new(data: &[u8]) -> Self {
+        EnvState {
+            data: data.to_vec(),
+        }
+    }
+
+    /// data() -> bytes
+    fn data<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        PyBytes::new(py, &self.data)
+    }
+
+    fn __reduce__<'p>(&self, py: Python<'p>) -> (PyObject, (&'p PyBytes,)) {
+        (
+            py.get_type::<EnvState>().to_object(py),
+            (PyBytes::new(py, &self.data),),
+        )
+    }
+
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+}
+
 #[pyclass]
 pub struct Env {
     dev: Device,
+    serial_log: Arc<Mutex<Vec<u8>>>,
+    reward: RewardConfig,
+    /// The mirror after the last `step`, which the next reward is computed against.
+    prev_mirror: Option<Vec<u8>>,
+    /// Frames each `step` holds its action for.
+    frame_skip: u32,
+}
+
+impl Env {
+    /// Score the next step against the current state. Call after anything that replaces the
+    /// emulator state, so its reward does not count the jump.
+    fn sync_prev_mirror(&mut self) {
+        self.prev_mirror = Some(self.dev.get_mirror());
+    }
+
+    /// `step` without Python objects, so it can run without the GIL.
+    fn advance(&mut self, action: u8) -> (Vec<u8>, f32, bool) {
+        // Apply action
+        self.dev.set_joypad_mask(action);
+
+        let mut total = 0.0;
+        let mut done = false;
+        let mut mirror = Vec::new();
+        for _ in 0..self.frame_skip {
+            // Step until next frame and ensure mirror updated
+            let _frame = self.dev.step_frame(); // we don't need the image here
+
+            mirror = self.dev.get_mirror();
+            if let Some(prev) = &self.prev_mirror {
+                let (reward, frame_done) = self.reward.reward(prev, &mirror);
+                total += reward;
+                done = frame_done;
+            }
+            self.prev_mirror = Some(mirror.clone());
+            // A game that stopped producing frames will not recover
+            done |= self.dev.stalled();
+            if done {
+                break;
+            }
+        }
+        (mirror, total, done)
+    }
 }
 
 #[pymethods]
 impl Env {
-    /// __new__(rom_path: str, *, skip_checksum: bool=False, classic_mode: bool=False)
+    /// __new__(rom_path: str, *, skip_checksum: bool=False, classic_mode: bool=False, render: bool=True, expose_debug: bool=False, frame_skip: int=1, seed: int=None, zero_wram: bool=False)
+    /// With render=False no frame buffer is kept: frames returned by the step methods are empty.
+    /// With expose_debug=True mirrors include the hidden/debug region.
+    /// Each step holds its action for frame_skip frames.
+    /// seed chooses the initial WRAM contents; envs with the same seed start out identical.
+    /// zero_wram=True fills WRAM with zeros instead and cannot be combined with seed.
     #[new]
-    fn new(rom_path: String, skip_checksum: Option<bool>, classic_mode: Option<bool>) -> PyResult<Self> {
+    #[pyo3(signature = (rom_path, *, skip_checksum=None, classic_mode=None, render=None, expose_debug=None, frame_skip=None, seed=None, zero_wram=None))]
+    fn new(
+        rom_path: String,
+        skip_checksum: Option<bool>,
+        classic_mode: Option<bool>,
+        render: Option<bool>,
+        expose_debug: Option<bool>,
+        frame_skip: Option<u32>,
+        seed: Option<u32>,
+        zero_wram: Option<bool>,
+    ) -> PyResult<Self> {
+        let frame_skip = frame_skip.unwrap_or(1);
+        if frame_skip == 0 {
+            return Err(exceptions::PyValueError::new_err(
+                "frame_skip must be at least 1",
+            ));
+        }
+        let wram_seed = match (zero_wram.unwrap_or(false), seed) {
+            (false, seed) => Some(seed.unwrap_or(DEFAULT_WRAM_SEED)),
+            (true, None) => None,
+            (true, Some(_)) => {
+                return Err(exceptions::PyValueError::new_err(
+                    "seed and zero_wram cannot be combined",
+                ))
+            }
+        };
         let skip = skip_checksum.unwrap_or(false);
         let classic = classic_mode.unwrap_or(false);
+        let render = render.unwrap_or(true);
 
         let dev_res = if classic {
             Device::new_cgb(&rom_path, skip, None)
@@ -40,13 +265,64 @@ impl Env {
         };
 
         match dev_res {
-            Ok(dev) => Ok(Env { dev }),
-            Err(e) => Err(PyErr::new::<exceptions::PyRuntimeError, _>(format!("Failed to create Device: {}", e))),
+            Ok(dev) => {
+                let mut dev = dev
+                    .with_render(render)
+                    .with_wram_seed(wram_seed);
+                dev.set_mirror_debug(expose_debug.unwrap_or(false));
+                Ok(Env {
+                    dev,
+                    serial_log: Arc::new(Mutex::new(Vec::new())),
+                    reward: RewardConfig::default(),
+                    prev_mirror: None,
+                    frame_skip,
+                })
+            }
+            Err(e) => Err(load_error(e)),
         }
     }
 
+    /// reset()
+    /// Power-on reset, or return to the state captured by `set_reset_anchor()`. The next
+    /// step's reward is computed against the reset state.
     fn reset(&mut self) -> PyResult<()> {
-        self.dev.reset();
+        guard(|| {
+            self.dev.reset();
+            self.sync_prev_mirror();
+            Ok(())
+        })
+    }
+
+    /// set_reset_anchor()
+    /// Makes the current state the target of subsequent `reset()` calls.
+    fn set_reset_anchor(&mut self) -> PyResult<()> {
+        self.dev.set_reset_anchor();
+        Ok(())
+    }
+
+    /// reset_gpu()
+    /// Resets only the LCD, VRAM and OAM.
+    fn reset_gpu(&mut self) -> PyResult<()> {
+        self.dev.reset_gpu();
+        Ok(())
+    }
+
+    /// reset_timers()
+    fn reset_timers(&mut self) -> PyResult<()> {
+        self.dev.reset_timers();
+        Ok(())
+    }
+
+    /// reset_apu()
+    /// Resets only the sound hardware, keeping game progress.
+    fn reset_apu(&mut self) -> PyResult<()> {
+        self.dev.reset_apu();
+        Ok(())
+    }
+
+    /// clear_reset_anchor()
+    fn clear_reset_anchor(&mut self) -> PyResult<()> {
+        self.dev.clear_reset_anchor();
         Ok(())
     }
 
@@ -56,25 +332,873 @@ impl Env {
         Ok(())
     }
 
+    /// read_bcd(addr: int, num_bytes: int) -> bytes
+    /// Raw bytes of a BCD field; decode them with rgirl_env.decode_bcd().
+    fn read_bcd<'p>(&mut self, py: Python<'p>, addr: u16, num_bytes: usize) -> &'p PyBytes {
+        PyBytes::new(py, &self.dev.read_bcd(addr, num_bytes))
+    }
+
+    /// read_memory(addr: int, length: int) -> bytes
+    /// Bytes as the CPU reads them from addr on, e.g. for observations the mirror lacks.
+    fn read_memory<'p>(
+        &mut self,
+        py: Python<'p>,
+        addr: u16,
+        length: usize,
+    ) -> PyResult<&'p PyBytes> {
+        check_range(addr, length)?;
+        let data: Vec<u8> = (0..length)
+            .map(|i| self.dev.read_byte(addr + i as u16))
+            .collect();
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// write_memory(addr: int, data: bytes)
+    /// Writes like the CPU would, so writes to ROM go to the cartridge's registers.
+    fn write_memory(&mut self, addr: u16, data: &[u8]) -> PyResult<()> {
+        check_range(addr, data.len())?;
+        for (i, &b) in data.iter().enumerate() {
+            self.dev.write_byte(addr + i as u16, b);
+        }
+        Ok(())
+    }
+
+    /// valid_actions() -> list[int]
+    /// All action masks without Left+Right or Up+Down pressed together.
+    fn valid_actions(&self) -> Vec<u8> {
+        Device::valid_action_set()
+    }
+
     /// step(action: u8) -> (mirror_bytes, reward, done)
+    /// Holds `action` for frame_skip frames, summing their rewards, and returns the last mirror.
+    /// Reward and done come from the mirror changes, see set_reward_weights; done ends it early.
+    /// A stalled frame (see stalled()) is done as well.
     fn step<'p>(&mut self, py: Python<'p>, action: u8) -> PyResult<(&'p PyBytes, f32, bool)> {
-        // Apply action
-        self.dev.set_joypad_mask(action);
+        let (mirror, reward, done) = guard(|| Ok(self.advance(action)))?;
+        Ok((PyBytes::new(py, &mirror), reward, done))
+    }
+
+    /// set_reward_weights(weights: dict[str, float], done_on_empty_party: bool=True)
+    /// Keys "money", "badges" and "enemy_hp" (per unit gained); missing ones are 0.
+    fn set_reward_weights(
+        &mut self,
+        weights: HashMap<String, f32>,
+        done_on_empty_party: Option<bool>,
+    ) -> PyResult<()> {
+        let mut config = RewardConfig::zero();
+        for (key, weight) in weights {
+            match key.as_str() {
+                "money" => config.money = weight,
+                "badges" => config.badges = weight,
+                "enemy_hp" => config.enemy_hp = weight,
+                _ => {
+                    return Err(exceptions::PyValueError::new_err(format!(
+                        "unknown reward weight {:?}",
+                        key
+                    )))
+                }
+            }
+        }
+        config.done_on_empty_party = done_on_empty_party.unwrap_or(true);
+        self.reward = config;
+        Ok(())
+    }
+
+    /// reward_weights() -> dict
+    /// The current weights and "done_on_empty_party".
+    fn reward_weights<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("money", self.reward.money)?;
+        dict.set_item("badges", self.reward.badges)?;
+        dict.set_item("enemy_hp", self.reward.enemy_hp)?;
+        dict.set_item("done_on_empty_party", self.reward.done_on_empty_party)?;
+        Ok(dict)
+    }
+
+    /// run_episode(actions: list[int], every: int=1) -> dict
+    /// Resets, plays one action per frame and returns {"mirrors", "frames", "reward", "done"}.
+    /// Scored like step, it stops early when done. The next step is scored against its end.
+    fn run_episode<'p>(
+        &mut self,
+        py: Python<'p>,
+        actions: Vec<u8>,
+        every: Option<usize>,
+    ) -> PyResult<&'p PyDict> {
+        let reward = self.reward.clone();
+        let result = guard(|| {
+            Ok(self
+                .dev
+                .run_episode_with(&actions, every.unwrap_or(1), |prev, mirror| {
+                    reward.reward(prev, mirror)
+                }))
+        })?;
+        self.sync_prev_mirror();
+        let mirrors: Vec<&PyBytes> = result.mirrors.iter().map(|m| PyBytes::new(py, m)).collect();
+        let dict = PyDict::new(py);
+        dict.set_item("mirrors", mirrors)?;
+        dict.set_item("frames", result.frames)?;
+        dict.set_item("reward", result.total_reward)?;
+        dict.set_item("done", result.done)?;
+        Ok(dict)
+    }
+
+    /// attach_serial(stdout: bool=False, printer: bool=False, log: bool=False)
+    /// Replaces the serial handlers with the selected combination. The printer, when
+    /// selected, answers the game; the others only observe the bytes.
+    fn attach_serial(
+        &mut self,
+        stdout: Option<bool>,
+        printer: Option<bool>,
+        log: Option<bool>,
+    ) -> PyResult<()> {
+        self.dev.unset_serial_callback();
+        if printer.unwrap_or(false) {
+            self.dev.attach_printer();
+        }
+        if stdout.unwrap_or(false) {
+            self.dev.add_serial_callback(Box::new(StdoutPrinter));
+        }
+        if log.unwrap_or(false) {
+            self.dev
+                .add_serial_callback(Box::new(SerialLog(self.serial_log.clone())));
+        }
+        Ok(())
+    }
+
+    /// serial_log() -> bytes
+    /// Returns and clears the bytes captured since the last call (requires `log=True`).
+    fn serial_log<'p>(&mut self, py: Python<'p>) -> PyResult<&'p PyBytes> {
+        let bytes = std::mem::take(&mut *self.serial_log.lock().unwrap());
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// title() -> str
+    /// The game title from the cartridge header.
+    fn title(&self) -> PyResult<String> {
+        Ok(self.dev.cart_title())
+    }
+
+    /// ram_size() -> int
+    fn ram_size(&self) -> PyResult<usize> {
+        Ok(self.dev.ram_size())
+    }
+
+    /// speed_ratio(frames: int) -> float
+    /// Runs `frames` frames and returns emulated time divided by wall-clock time.
+    fn speed_ratio(&mut self, frames: u32) -> PyResult<f64> {
+        guard(|| Ok(self.dev.speed_ratio(frames)))
+    }
+
+    /// step_collect(action: u8, n: int) -> list[bytes]
+    /// Holds `action` for `n` frames and returns every rendered frame (~69KB each). Stops
+    /// after a stalled frame (see stalled()), returning fewer frames.
+    fn step_collect<'p>(
+        &mut self,
+        py: Python<'p>,
+        action: u8,
+        n: u32,
+    ) -> PyResult<Vec<&'p PyBytes>> {
+        guard(|| {
+            self.dev.set_joypad_mask(action);
+            let frames = self.dev.step_frames_collect(n);
+            Ok(frames.iter().map(|f| PyBytes::new(py, f)).collect())
+        })
+    }
 
-        // Step until next frame and ensure mirror updated
-        let _frame = self.dev.step_frame(); // we don't need the image here
+    /// get_screen() -> bytes
+    /// The current 160x144 frame, row by row, in the set_frame_format layout ("rgb" by
+    /// default, 8 bits per channel). Reshape it with numpy to screen_shape(). Empty with render=False.
+    fn get_screen<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        PyBytes::new(py, &self.dev.get_gpu_data())
+    }
+
+    /// screen_shape() -> (int, int, int)
+    /// (height, width, channels) of get_screen(): (144, 160, 3) for RGB, 4 channels with alpha.
+    fn screen_shape(&self) -> (usize, usize, usize) {
+        let channels = self.dev.frame_format().bytes_per_pixel();
+        (SCREEN_H, SCREEN_W, channels)
+    }
+
+    /// set_frame_format(fmt: str)
+    /// Layout of returned frames: "rgb" (default), "bgr", "rgba" or "bgra".
+    fn set_frame_format(&mut self, fmt: &str) -> PyResult<()> {
+        let format = match fmt.to_ascii_lowercase().as_str() {
+            "rgb" => FrameFormat::Rgb,
+            "bgr" => FrameFormat::Bgr,
+            "rgba" => FrameFormat::Rgba,
+            "bgra" => FrameFormat::Bgra,
+            _ => {
+                return Err(exceptions::PyValueError::new_err(format!(
+                    "unknown frame format {:?}",
+                    fmt
+                )))
+            }
+        };
+        self.dev.set_frame_format(format);
+        Ok(())
+    }
+
+    /// set_dmg_palette(colors: list[tuple[int, int, int]])
+    /// RGB of the four shades of classic games, lightest first. Greyscale by default.
+    fn set_dmg_palette(&mut self, colors: [(u8, u8, u8); 4]) {
+        self.dev.set_dmg_palette(colors.map(|(r, g, b)| [r, g, b]));
+    }
+
+    /// timing() -> dict
+    /// {"frame_count", "total_cycles", "game_seconds", "rtc_seconds"}; rtc_seconds may be None.
+    fn timing<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let timing = self.dev.timing_info();
+        let dict = PyDict::new(py);
+        dict.set_item("frame_count", timing.frame_count)?;
+        dict.set_item("total_cycles", timing.total_cycles)?;
+        dict.set_item("game_seconds", timing.game_seconds)?;
+        dict.set_item("rtc_seconds", timing.rtc_seconds)?;
+        Ok(dict)
+    }
+
+    /// diagnostics() -> dict
+    /// {"healthy", "is_looping", "is_stopped", "is_halted", "last_illegal_opcode", "hdma_errors",
+    /// "lcd_enabled", "frame_count", "total_cycles"}; healthy is False for a wedged game.
+    fn diagnostics<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let report = self.dev.diagnostics();
+        let dict = PyDict::new(py);
+        dict.set_item("healthy", report.healthy())?;
+        dict.set_item("is_looping", report.is_looping)?;
+        dict.set_item("is_stopped", report.is_stopped)?;
+        dict.set_item("is_halted", report.is_halted)?;
+        dict.set_item("last_illegal_opcode", report.last_illegal_opcode)?;
+        dict.set_item("hdma_errors", report.hdma_errors)?;
+        dict.set_item("lcd_enabled", report.lcd_enabled)?;
+        dict.set_item("frame_count", report.frame_count)?;
+        dict.set_item("total_cycles", report.total_cycles)?;
+        Ok(dict)
+    }
+
+    /// brightness() -> int
+    /// Mean brightness of the current frame, 0 (black) to 255 (white).
+    fn brightness(&self) -> PyResult<u8> {
+        Ok(self.dev.brightness())
+    }
+
+    /// bg_tile_at(x: int, y: int) -> (tile_index, color)
+    /// Background tile and raw 2bpp color number under screen pixel (x, y).
+    fn bg_tile_at(&self, x: u8, y: u8) -> PyResult<(u8, u8)> {
+        Ok(self.dev.bg_tile_at(x, y))
+    }
 
-        // Read mirror
-        let mirror_vec = self.dev.get_mirror();
-        let pyb = PyBytes::new(py, &mirror_vec);
+    /// player_position() -> Optional[dict]
+    /// {"map_bank", "map_id", "x", "y"} from the Gen 2 layout, None while no map is loaded.
+    fn player_position<'p>(&mut self, py: Python<'p>) -> PyResult<Option<&'p PyDict>> {
+        let pos = match self.dev.player_position() {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("map_bank", pos.map_bank)?;
+        dict.set_item("map_id", pos.map_id)?;
+        dict.set_item("x", pos.x)?;
+        dict.set_item("y", pos.y)?;
+        Ok(Some(dict))
+    }
+
+    /// battle_state() -> Optional[dict]
+    /// {"in_battle", "enemy_species", "enemy_level", "enemy_hp", "enemy_max_hp", "player_hp"}
+    /// from the Gen 2 layout, None outside of battle.
+    fn battle_state<'p>(&mut self, py: Python<'p>) -> PyResult<Option<&'p PyDict>> {
+        let state = match self.dev.battle_state() {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("in_battle", state.in_battle)?;
+        dict.set_item("enemy_species", state.enemy_species)?;
+        dict.set_item("enemy_level", state.enemy_level)?;
+        dict.set_item("enemy_hp", state.enemy_hp)?;
+        dict.set_item("enemy_max_hp", state.enemy_max_hp)?;
+        dict.set_item("player_hp", state.player_hp)?;
+        Ok(Some(dict))
+    }
 
-        // Placeholder reward / done — compute in Python from mirror for now
-        Ok((pyb, 0.0_f32, false))
+    /// menu_cursor() -> Optional[int]
+    /// Selected item (0-based) of the open menu, None outside menus. Gen 2 Pokemon only.
+    fn menu_cursor(&mut self) -> PyResult<Option<u8>> {
+        Ok(self.dev.menu_cursor())
+    }
+
+    /// party_member(slot: int) -> Optional[dict]
+    /// {"species", "level", "hp", "max_hp", "status", "moves"} from the mirror, None if the
+    /// party has no such slot.
+    fn party_member<'p>(&self, py: Python<'p>, slot: usize) -> PyResult<Option<&'p PyDict>> {
+        let member = match self.dev.party_member(slot) {
+            Some(member) => member,
+            None => return Ok(None),
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("species", member.species)?;
+        dict.set_item("level", member.level)?;
+        dict.set_item("hp", member.hp)?;
+        dict.set_item("max_hp", member.max_hp)?;
+        dict.set_item("status", member.status)?;
+        dict.set_item("moves", member.moves.to_vec())?;
+        Ok(Some(dict))
+    }
+
+    /// party_status(slot: int) -> Optional[dict]
+    /// {"sleep_turns", "poison", "burn", "freeze", "paralysis"}, None past the party size.
+    fn party_status<'p>(&self, py: Python<'p>, slot: usize) -> PyResult<Option<&'p PyDict>> {
+        let status = match self.dev.party_status_flags(slot) {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("sleep_turns", status.sleep_turns)?;
+        dict.set_item("poison", status.poison)?;
+        dict.set_item("burn", status.burn)?;
+        dict.set_item("freeze", status.freeze)?;
+        dict.set_item("paralysis", status.paralysis)?;
+        Ok(Some(dict))
+    }
+
+    /// step_cycles(n: int) -> int
+    /// Runs at least n CPU cycles, ignoring frame boundaries, and returns how many ran.
+    fn step_cycles(&mut self, n: u32) -> PyResult<u32> {
+        guard(|| Ok(self.dev.step_cycles(n)))
+    }
+
+    /// skip_until(offset: int, max_frames: int) -> int
+    /// Steps with the current action held until mirror byte `offset` changes; returns the frames run.
+    fn skip_until(&mut self, offset: usize, max_frames: u32) -> PyResult<u32> {
+        if offset >= self.dev.get_mirror().len() {
+            return Err(exceptions::PyValueError::new_err(
+                "offset is outside of the mirror",
+            ));
+        }
+        guard(|| Ok(self.dev.run_until_mirror_change(offset, max_frames)))
+    }
+
+    /// wait_for_overworld(max_frames: int) -> bool
+    /// Steps, tapping B to close menus and dialogue, until the player can walk on the map.
+    fn wait_for_overworld(&mut self, max_frames: u64) -> PyResult<bool> {
+        guard(|| Ok(self.dev.wait_for_overworld(max_frames)))
+    }
+
+    /// set_player_position(x: int, y: int)
+    fn set_player_position(&mut self, x: u8, y: u8) -> PyResult<()> {
+        self.dev.set_player_position(x, y);
+        Ok(())
+    }
+
+    /// local_map(radius: int) -> bytes
+    /// Gen 2 map block ids in a (2*radius+1) square around the player, row-major.
+    fn local_map<'p>(&mut self, py: Python<'p>, radius: u8) -> PyResult<&'p PyBytes> {
+        Ok(PyBytes::new(py, &self.dev.local_map(radius)))
+    }
+
+    /// apu_step() -> int
+    /// Next APU frame sequencer step (0-7), 0 while audio is disabled.
+    fn apu_step(&self) -> PyResult<u8> {
+        Ok(self.dev.apu_step())
+    }
+
+    /// total_cycles() -> int
+    /// CPU cycles run since construction or the last reset().
+    fn total_cycles(&self) -> PyResult<u64> {
+        Ok(self.dev.total_cycles())
+    }
+
+    /// frame_count() -> int
+    /// Frames since construction or the last reset().
+    fn frame_count(&self) -> PyResult<u32> {
+        Ok(self.dev.frame_count())
+    }
+
+    /// frames_since_input_change() -> int
+    /// Frames stepped since the action last changed.
+    fn frames_since_input_change(&self) -> PyResult<u32> {
+        Ok(self.dev.frames_since_input_change())
+    }
+
+    /// enable_audio_capture(samples: int)
+    /// Keeps the last `samples` stereo samples (44100Hz) for `drain_audio()`.
+    fn enable_audio_capture(&mut self, samples: usize) -> PyResult<()> {
+        self.dev.enable_audio_capture(samples);
+        Ok(())
+    }
+
+    /// set_audio_buffer_limit(samples: int)
+    /// Most stereo samples kept waiting for a slow audio player; older ones are dropped.
+    fn set_audio_buffer_limit(&mut self, samples: usize) -> PyResult<()> {
+        self.dev.set_audio_buffer_limit(samples);
+        Ok(())
+    }
+
+    /// audio_buffer_overruns() -> int
+    /// Number of stereo samples dropped because of the buffer limit.
+    fn audio_buffer_overruns(&self) -> PyResult<u64> {
+        Ok(self.dev.audio_buffer_overruns())
+    }
+
+    /// drain_audio() -> list[int]
+    /// Captured samples since the last call, interleaved left/right as 16 bit integers.
+    fn drain_audio(&mut self) -> PyResult<Vec<i16>> {
+        Ok(self.dev.drain_audio())
+    }
+
+    /// requires_cgb() -> bool
+    fn requires_cgb(&self) -> PyResult<bool> {
+        Ok(self.dev.requires_cgb())
+    }
+
+    /// cpu_registers() -> dict
+    /// The 8-bit registers "a".."l", the pairs "af", "bc", "de", "hl", and "sp", "pc", the
+    /// flags "zf", "nf", "hf", "cf" and the "ime" and "halted" state.
+    fn cpu_registers<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        let regs = self.dev.cpu_registers();
+        let dict = PyDict::new(py);
+        dict.set_item("a", regs.a)?;
+        dict.set_item("f", regs.f)?;
+        dict.set_item("b", regs.b)?;
+        dict.set_item("c", regs.c)?;
+        dict.set_item("d", regs.d)?;
+        dict.set_item("e", regs.e)?;
+        dict.set_item("h", regs.h)?;
+        dict.set_item("l", regs.l)?;
+        dict.set_item("af", regs.af)?;
+        dict.set_item("bc", regs.bc)?;
+        dict.set_item("de", regs.de)?;
+        dict.set_item("hl", regs.hl)?;
+        dict.set_item("sp", regs.sp)?;
+        dict.set_item("pc", regs.pc)?;
+        dict.set_item("zf", regs.zf)?;
+        dict.set_item("nf", regs.nf)?;
+        dict.set_item("hf", regs.hf)?;
+        dict.set_item("cf", regs.cf)?;
+        dict.set_item("ime", regs.ime)?;
+        dict.set_item("halted", regs.halted)?;
+        Ok(dict)
+    }
+
+    /// set_layers(bg: bool, window: bool, sprites: bool)
+    /// Hides layers from the rendered frames, e.g. set_layers(False, False, True) for sprites only.
+    fn set_layers(&mut self, bg: bool, window: bool, sprites: bool) -> PyResult<()> {
+        self.dev.set_layers(bg, window, sprites);
+        Ok(())
+    }
+
+    /// div_counter() -> int
+    /// The internal 16-bit divider; DIV (0xFF04) is its upper byte.
+    fn div_counter(&self) -> PyResult<u16> {
+        Ok(self.dev.div_counter())
+    }
+
+    /// state_delta(base: bytes) -> bytes
+    /// The current state encoded as a difference from `base`, a state from `snapshot()`.
+    /// Only `apply_delta` with the very same base can decode it.
+    fn state_delta<'p>(&self, py: Python<'p>, base: &[u8]) -> PyResult<&'p PyBytes> {
+        Ok(PyBytes::new(py, &self.dev.state_delta(base)))
+    }
+
+    /// apply_delta(base: bytes, delta: bytes)
+    fn apply_delta(&mut self, base: &[u8], delta: &[u8]) -> PyResult<()> {
+        self.dev
+            .apply_delta(base, delta)
+            .map_err(exceptions::PyValueError::new_err)?;
+        self.sync_prev_mirror();
+        Ok(())
+    }
+
+    /// snapshot() -> bytes
+    /// The full emulator state. snapshot/restore is the state API; get_state/set_state and
+    /// save_state/load_state are built on it.
+    fn snapshot<'p>(&self, py: Python<'p>) -> PyResult<&'p PyBytes> {
+        Ok(PyBytes::new(py, &self.dev.snapshot()))
+    }
+
+    /// restore(data: bytes)
+    /// Returns to a state from snapshot(), keeping this Env's settings and serial log.
+    /// Raises ValueError if `data` is not a state. The next step is scored against it.
+    fn restore(&mut self, data: &[u8]) -> PyResult<()> {
+        self.dev
+            .restore(data)
+            .map_err(exceptions::PyValueError::new_err)?;
+        self.sync_prev_mirror();
+        Ok(())
+    }
+
+    /// wait_stable(stable_frames: int, max_frames: int) -> bool
+    /// Step with the current action held until the screen stays the same for `stable_frames`
+    /// frames. Returns False if it is still changing after `max_frames` frames.
+    fn wait_stable(&mut self, stable_frames: u32, max_frames: u64) -> PyResult<bool> {
+        guard(|| Ok(self.dev.wait_for_stable_screen(stable_frames, max_frames)))
+    }
+
+    /// frame_hash() -> int
+    fn frame_hash(&self) -> PyResult<u64> {
+        Ok(self.dev.frame_hash())
+    }
+
+    /// vram_hash() -> int
+    /// Hash of VRAM; changes with tile or tile map updates, not with scrolling.
+    fn vram_hash(&self) -> PyResult<u64> {
+        Ok(self.dev.vram_hash())
+    }
+
+    /// run_test_rom(max_frames: int) -> dict
+    /// {"result": "passed" | "failed" | "timeout", "transcript": str}, where the transcript
+    /// is everything the ROM sent over serial.
+    fn run_test_rom<'p>(&mut self, py: Python<'p>, max_frames: u32) -> PyResult<&'p PyDict> {
+        let result = guard(|| Ok(self.dev.run_test_rom(max_frames)))?;
+        let name = match result {
+            TestRomResult::Passed { .. } => "passed",
+            TestRomResult::Failed { .. } => "failed",
+            TestRomResult::Timeout { .. } => "timeout",
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("result", name)?;
+        dict.set_item("transcript", result.transcript())?;
+        Ok(dict)
+    }
+
+    /// ram_just_saved() -> bool
+    /// True once after the game finished writing its save, the moment to persist it.
+    fn ram_just_saved(&mut self) -> PyResult<bool> {
+        Ok(self.dev.ram_just_saved())
+    }
+
+    /// set_max_step_cycles(cycles: int)
+    /// Most cycles a step may run waiting for a frame (default: two frames worth).
+    fn set_max_step_cycles(&mut self, cycles: u64) -> PyResult<()> {
+        self.dev.set_max_step_cycles(cycles);
+        Ok(())
+    }
+
+    /// last_opcode() -> int
+    /// Opcode of the most recently executed instruction, 0xCB for CB-prefixed ones.
+    fn last_opcode(&self) -> PyResult<u8> {
+        Ok(self.dev.last_opcode())
+    }
+
+    /// detected_mode() -> str
+    /// "classic", "color" or "color_as_classic" (a CGB running a game without CGB support).
+    fn detected_mode(&self) -> PyResult<&'static str> {
+        Ok(match self.dev.detected_mode() {
+            GbMode::Classic => "classic",
+            GbMode::Color => "color",
+            GbMode::ColorAsClassic => "color_as_classic",
+        })
+    }
+
+    /// cgb_features_blocked() -> bool
+    /// Whether the game wrote CGB only registers that the current mode ignores.
+    fn cgb_features_blocked(&self) -> PyResult<bool> {
+        Ok(self.dev.cgb_features_blocked())
+    }
+
+    /// stalled() -> bool
+    /// Whether the last step gave up waiting for a frame, e.g. because the LCD is off.
+    fn stalled(&self) -> PyResult<bool> {
+        Ok(self.dev.stalled())
+    }
+
+    /// get_state() -> EnvState
+    /// snapshot() wrapped in a picklable EnvState.
+    fn get_state(&self) -> EnvState {
+        EnvState {
+            data: self.dev.snapshot(),
+        }
+    }
+
+    /// set_state(state: EnvState)
+    /// restore() for an EnvState.
+    fn set_state(&mut self, state: &EnvState) -> PyResult<()> {
+        self.restore(&state.data)
+    }
+
+    /// save_state() -> bytes
+    /// Same as snapshot().
+    fn save_state<'p>(&self, py: Python<'p>) -> PyResult<&'p PyBytes> {
+        self.snapshot(py)
+    }
+
+    /// load_state(data: bytes)
+    /// Same as restore().
+    fn load_state(&mut self, data: &[u8]) -> PyResult<()> {
+        self.restore(data)
+    }
+
+    /// set_mirror_spec(size: int, fields: list[(offset, addr, width, encoding)], visible_size: int=size)
+    /// Mirror other game memory; encoding is "le", "be" or "bcd". Bytes 0-3 hold the frame counter.
+    fn set_mirror_spec(
+        &mut self,
+        size: usize,
+        fields: Vec<(usize, u16, usize, String)>,
+        visible_size: Option<usize>,
+    ) -> PyResult<()> {
+        let fields = fields
+            .into_iter()
+            .map(|(offset, addr, width, encoding)| {
+                let encoding = match encoding.as_str() {
+                    "le" => FieldEncoding::LittleEndian,
+                    "be" => FieldEncoding::BigEndian,
+                    "bcd" => FieldEncoding::Bcd,
+                    _ => {
+                        return Err(exceptions::PyValueError::new_err(format!(
+                            "unknown field encoding {:?}",
+                            encoding
+                        )))
+                    }
+                };
+                Ok(MirrorField::new(offset, addr, width, encoding))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let mut spec = MirrorSpec::new(size, fields);
+        spec.visible_size = visible_size.unwrap_or(size);
+        self.dev
+            .set_mirror_spec(spec)
+            .map_err(exceptions::PyValueError::new_err)
+    }
+
+    /// set_mirror_write_tracking(enabled: bool)
+    /// Record writes to the mirror's source addresses, for `mirror_is_fresh`.
+    fn set_mirror_write_tracking(&mut self, enabled: bool) -> PyResult<()> {
+        self.dev.set_mirror_write_tracking(enabled);
+        Ok(())
+    }
+
+    /// mirror_is_fresh() -> bool
+    /// False if the last frame wrote none of the mirror's sources (needs tracking on).
+    fn mirror_is_fresh(&self) -> PyResult<bool> {
+        Ok(self.dev.mirror_is_fresh())
+    }
+
+    /// add_cheat(code: str)
+    /// Applies a Game Genie ("ABC-DEF-GHI") or GameShark ("01VVLLHH") code until clear_cheats().
+    fn add_cheat(&mut self, code: &str) -> PyResult<()> {
+        self.dev
+            .add_cheat(code)
+            .map_err(exceptions::PyValueError::new_err)
+    }
+
+    /// clear_cheats()
+    fn clear_cheats(&mut self) -> PyResult<()> {
+        self.dev.clear_cheats();
+        Ok(())
+    }
+
+    /// inject_fault(addr: int, value: int)
+    /// Testing only: corrupts WRAM/cartridge RAM at `addr`, even where the game cannot write.
+    fn inject_fault(&mut self, addr: u16, value: u8) -> PyResult<()> {
+        self.dev.inject_fault(addr, value);
+        Ok(())
+    }
+
+    /// enable_random_faults(rate: float, seed: int)
+    /// Testing only: flips `rate` random WRAM bits per frame on average; 0 turns it off.
+    fn enable_random_faults(&mut self, rate: f64, seed: u64) -> PyResult<()> {
+        self.dev.enable_random_faults(rate, seed);
+        Ok(())
+    }
+
+    /// trigger_panic(message: str)
+    /// Testing only: panics with `message` inside the emulator, raising RuntimeError.
+    fn trigger_panic(&mut self, message: &str) -> PyResult<()> {
+        guard(|| panic!("{}", message))
     }
 
     /// get_mirror() -> bytes
     fn get_mirror<'p>(&self, py: Python<'p>) -> PyResult<&'p PyBytes> {
-        let mirror_vec = self.dev.get_mirror();
-        Ok(PyBytes::new(py, &mirror_vec))
+        guard(|| {
+            let mirror_vec = self.dev.get_mirror();
+            Ok(PyBytes::new(py, &mirror_vec))
+        })
+    }
+}
+
+/// Steps each env with its action, splitting them over one thread per core. The envs share
+/// no state, so no locking is needed.
+fn step_parallel(envs: &mut [Env], actions: &[u8]) -> PyResult<Vec<(Vec<u8>, f32, bool)>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = envs.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = envs
+            .chunks_mut(chunk)
+            .zip(actions.chunks(chunk))
+            .map(|(envs, actions)| {
+                scope.spawn(move || {
+                    envs.iter_mut()
+                        .zip(actions)
+                        .map(|(env, &action)| guard(|| Ok(env.advance(action))))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("panics are caught by guard"))
+            .collect()
+    })
+}
+
+/// Several independent `Env`s on the same ROM, stepped together. Each keeps a done flag,
+/// set when its step reports done or by `mark_done`, until it is reset.
+#[pyclass]
+pub struct VecEnv {
+    envs: Vec<Env>,
+    done: Vec<bool>,
+}
+
+impl VecEnv {
+    fn check_actions(&self, actions: &[u8]) -> PyResult<()> {
+        if actions.len() != self.envs.len() {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "Expected {} actions, got {}",
+                self.envs.len(),
+                actions.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn reset_env<'p>(&mut self, py: Python<'p>, index: usize) -> PyResult<&'p PyBytes> {
+        let env = &mut self.envs[index];
+        env.reset()?;
+        self.done[index] = false;
+        Ok(PyBytes::new(py, &env.dev.get_mirror()))
+    }
+}
+
+#[pymethods]
+impl VecEnv {
+    /// __new__(rom_path: str, num_envs: int, *, skip_checksum: bool=False, classic_mode: bool=False, render: bool=True, expose_debug: bool=False, frame_skip: int=1, seed: int=None, zero_wram: bool=False)
+    #[new]
+    #[pyo3(signature = (rom_path, num_envs, *, skip_checksum=None, classic_mode=None, render=None, expose_debug=None, frame_skip=None, seed=None, zero_wram=None))]
+    fn new(
+        rom_path: String,
+        num_envs: usize,
+        skip_checksum: Option<bool>,
+        classic_mode: Option<bool>,
+        render: Option<bool>,
+        expose_debug: Option<bool>,
+        frame_skip: Option<u32>,
+        seed: Option<u32>,
+        zero_wram: Option<bool>,
+    ) -> PyResult<Self> {
+        let envs = (0..num_envs)
+            .map(|_| {
+                Env::new(
+                    rom_path.clone(),
+                    skip_checksum,
+                    classic_mode,
+                    render,
+                    expose_debug,
+                    frame_skip,
+                    seed,
+                    zero_wram,
+                )
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(VecEnv {
+            envs,
+            done: vec![false; num_envs],
+        })
+    }
+
+    /// step(actions: list[int]) -> list[(mirror_bytes, reward, done)]
+    /// One action per env.
+    fn step<'p>(
+        &mut self,
+        py: Python<'p>,
+        actions: Vec<u8>,
+    ) -> PyResult<Vec<(&'p PyBytes, f32, bool)>> {
+        self.check_actions(&actions)?;
+        let mut results = Vec::with_capacity(actions.len());
+        for (i, action) in actions.into_iter().enumerate() {
+            let result = self.envs[i].step(py, action)?;
+            self.done[i] |= result.2;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// step_batch(actions: list[int]) -> (list[mirror_bytes], list[float], list[bool])
+    /// Like step, but the envs run in parallel on all cores, without holding the GIL.
+    fn step_batch<'p>(
+        &mut self,
+        py: Python<'p>,
+        actions: Vec<u8>,
+    ) -> PyResult<(Vec<&'p PyBytes>, Vec<f32>, Vec<bool>)> {
+        self.check_actions(&actions)?;
+        let envs = &mut self.envs;
+        let results = py.allow_threads(|| step_parallel(envs, &actions))?;
+        let mut mirrors = Vec::with_capacity(results.len());
+        let mut rewards = Vec::with_capacity(results.len());
+        let mut dones = Vec::with_capacity(results.len());
+        for (i, (mirror, reward, done)) in results.into_iter().enumerate() {
+            self.done[i] |= done;
+            mirrors.push(PyBytes::new(py, &mirror));
+            rewards.push(reward);
+            dones.push(done);
+        }
+        Ok((mirrors, rewards, dones))
+    }
+
+    /// reset_batch() -> list[mirror_bytes]
+    /// Same as reset().
+    fn reset_batch<'p>(&mut self, py: Python<'p>) -> PyResult<Vec<&'p PyBytes>> {
+        self.reset(py)
+    }
+
+    /// num_envs() -> int
+    fn num_envs(&self) -> usize {
+        self.envs.len()
+    }
+
+    /// reset() -> list[mirror_bytes]
+    /// Resets every env.
+    fn reset<'p>(&mut self, py: Python<'p>) -> PyResult<Vec<&'p PyBytes>> {
+        (0..self.envs.len())
+            .map(|i| self.reset_env(py, i))
+            .collect()
+    }
+
+    /// reset_done() -> (list[int], list[mirror_bytes])
+    /// Resets only the envs flagged done and returns their indices and new observations.
+    fn reset_done<'p>(&mut self, py: Python<'p>) -> PyResult<(Vec<usize>, Vec<&'p PyBytes>)> {
+        let indices: Vec<usize> = (0..self.envs.len()).filter(|&i| self.done[i]).collect();
+        let observations = indices
+            .iter()
+            .map(|&i| self.reset_env(py, i))
+            .collect::<PyResult<_>>()?;
+        Ok((indices, observations))
+    }
+
+    /// mark_done(index: int)
+    /// Flags an env as done, e.g. when the episode hit a time limit.
+    fn mark_done(&mut self, index: usize) -> PyResult<()> {
+        match self.done.get_mut(index) {
+            Some(done) => {
+                *done = true;
+                Ok(())
+            }
+            None => Err(exceptions::PyIndexError::new_err("env index out of range")),
+        }
+    }
+
+    /// done() -> list[bool]
+    fn done(&self) -> PyResult<Vec<bool>> {
+        Ok(self.done.clone())
+    }
+
+    /// set_reset_anchor()
+    /// Makes the current state of every env the target of its resets.
+    fn set_reset_anchor(&mut self) -> PyResult<()> {
+        for env in self.envs.iter_mut() {
+            env.set_reset_anchor()?;
+        }
+        Ok(())
+    }
+
+    fn __len__(&self) -> usize {
+        self.envs.len()
     }
 }
@@ -0,0 +1,35 @@
+import os
+import tempfile
+import unittest
+
+
+def make_rom(cart_type=0x00, cgb_flag=0x00, size=0x8000, checksum=True, code=b"\x18\xfe"):
+    data = bytearray(size)
+    data[0x100:0x100 + len(code)] = code[: max(0, size - 0x100)]
+    if size >= 0x150:
+        data[0x143] = cgb_flag
+        data[0x147] = cart_type
+        if checksum:
+            value = 0
+            for b in data[0x134:0x14D]:
+                value = (value - b - 1) & 0xFF
+            data[0x14D] = value
+        else:
+            data[0x14D] = 0x55
+    return bytes(data)
+
+
+class RomTestCase(unittest.TestCase):
+    """Gives each test a temporary directory to write ROMs into."""
+
+    def setUp(self):
+        tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(tmp.cleanup)
+        self.tmp = tmp.name
+
+    def write_rom(self, data=None, name="test.gb"):
+        """Write `data` (the idle ROM by default) to a file and return its path."""
+        path = os.path.join(self.tmp, name)
+        with open(path, "wb") as f:
+            f.write(make_rom() if data is None else data)
+        return path
@@ -0,0 +1,59 @@
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase, make_rom
+
+
+class RunEpisodeTest(RomTestCase):
+    def test_trajectory_matches_actions(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        env.set_reset_anchor()
+        result = env.run_episode([0, 1, 2, 0, 16])
+        self.assertEqual(len(result["mirrors"]), 5)
+        self.assertEqual(result["frames"], 5)
+        self.assertFalse(result["done"])
+        again = env.run_episode([0, 1, 2, 0, 16], every=2)
+        self.assertEqual(again["mirrors"], result["mirrors"][1::2] + result["mirrors"][-1:])
+
+    def test_frame_skip_advances_frame_counter(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path, frame_skip=4)
+        counter = lambda mirror: int.from_bytes(mirror[:4], "little")
+        first, _, _ = env.step(0)
+        second, _, done = env.step(0)
+        self.assertEqual(counter(second) - counter(first), 4)
+        self.assertFalse(done)
+        with self.assertRaises(ValueError):
+            rgirl_env.Env(path, frame_skip=0)
+
+    def test_step_cycles_updates_mirror(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        counter = lambda: int.from_bytes(env.get_mirror()[:4], "little")
+        ran = env.step_cycles(100)
+        self.assertTrue(100 <= ran < 124)
+        before = counter()
+        env.step_cycles(70224 * 2)
+        self.assertGreaterEqual(counter() - before, 1)
+
+    def test_skip_until_frame_counter_changes(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        self.assertEqual(env.skip_until(0, 10), 1)
+        # Party count, never written by the idle ROM
+        self.assertEqual(env.skip_until(8, 5), 5)
+        with self.assertRaises(ValueError):
+            env.skip_until(len(env.get_mirror()), 5)
+
+    def test_stalled_step_is_done(self):
+        # LCDC = 0, then loop forever: no frame ever completes
+        path = self.write_rom(make_rom(code=b"\xaf\xe0\x40\x18\xfe"))
+        env = rgirl_env.Env(path, frame_skip=3)
+        _, _, done = env.step(0)
+        self.assertTrue(done)
+        self.assertTrue(env.stalled())
+
+
+if __name__ == "__main__":
+    unittest.main()
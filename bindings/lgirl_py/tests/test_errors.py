@@ -0,0 +1,62 @@
+import os
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase, make_rom
+
+
+class LoadErrorTest(RomTestCase):
+    def test_unsupported_mbc(self):
+        path = self.write_rom(make_rom(cart_type=0xFF))
+        with self.assertRaises(rgirl_env.UnsupportedMbcError):
+            rgirl_env.Env(path)
+
+    def test_bad_checksum(self):
+        path = self.write_rom(make_rom(checksum=False))
+        with self.assertRaises(rgirl_env.BadChecksumError):
+            rgirl_env.Env(path)
+
+    def test_bad_checksum_skipped(self):
+        path = self.write_rom(make_rom(checksum=False))
+        rgirl_env.Env(path, skip_checksum=True)
+
+    def test_rom_too_small(self):
+        path = self.write_rom(make_rom(size=0x100))
+        with self.assertRaises(rgirl_env.RomTooSmallError):
+            rgirl_env.Env(path)
+
+    def test_rom_size_mismatch(self):
+        path = self.write_rom(make_rom(size=0x6000))
+        with self.assertRaises(rgirl_env.RomSizeMismatchError):
+            rgirl_env.Env(path)
+        rgirl_env.Env(path, skip_checksum=True)
+
+    def test_classic_mode_rejected(self):
+        path = self.write_rom(make_rom(cgb_flag=0xC0))
+        with self.assertRaises(rgirl_env.ClassicModeError):
+            rgirl_env.Env(path)
+
+    def test_missing_file(self):
+        with self.assertRaises(rgirl_env.RomLoadError):
+            rgirl_env.Env(os.path.join(self.tmp, "missing.gb"))
+
+    def test_requires_cgb(self):
+        dmg = self.write_rom()
+        self.assertFalse(rgirl_env.requires_cgb(dmg))
+        cgb = self.write_rom(make_rom(cgb_flag=0xC0), "cgb.gb")
+        self.assertTrue(rgirl_env.requires_cgb(cgb))
+        self.assertTrue(rgirl_env.Env(cgb, classic_mode=True).requires_cgb())
+
+    def test_options_are_keyword_only(self):
+        path = self.write_rom()
+        with self.assertRaises(TypeError):
+            rgirl_env.Env(path, True)
+        with self.assertRaises(TypeError):
+            rgirl_env.VecEnv(path, 2, True)
+
+    def test_errors_are_runtime_errors(self):
+        self.assertTrue(issubclass(rgirl_env.UnsupportedMbcError, RuntimeError))
+
+
+if __name__ == "__main__":
+    unittest.main()
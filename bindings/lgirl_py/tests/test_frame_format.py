@@ -0,0 +1,33 @@
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase
+
+
+class FrameFormatTest(RomTestCase):
+    def test_rgba_frames_have_four_channels(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        (rgb,) = env.step_collect(0, 1)
+        env.set_frame_format("rgba")
+        (rgba,) = env.step_collect(0, 1)
+        self.assertEqual(len(rgb), 160 * 144 * 3)
+        self.assertEqual(len(rgba), 160 * 144 * 4)
+        self.assertTrue(all(a == 255 for a in rgba[3::4]))
+        with self.assertRaises(ValueError):
+            env.set_frame_format("yuv")
+
+    def test_screen_matches_shape(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        env.step(0)
+        for fmt, channels in (("rgb", 3), ("bgra", 4)):
+            env.set_frame_format(fmt)
+            height, width, bpp = env.screen_shape()
+            self.assertEqual((height, width, bpp), (144, 160, channels))
+            self.assertEqual(len(env.get_screen()), width * height * bpp)
+        self.assertEqual(len(rgirl_env.Env(path, render=False).get_screen()), 0)
+
+
+if __name__ == "__main__":
+    unittest.main()
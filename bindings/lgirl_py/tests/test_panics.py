@@ -0,0 +1,30 @@
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase, make_rom
+
+
+class PanicTest(RomTestCase):
+    def test_panic_raises_runtime_error(self):
+        env = rgirl_env.Env(self.write_rom())
+        with self.assertRaises(RuntimeError) as ctx:
+            env.trigger_panic("boom")
+        self.assertIn("Emulator panicked: boom", str(ctx.exception))
+        # The env stays usable afterwards
+        env.step(0)
+
+
+class IllegalOpcodeTest(RomTestCase):
+    def test_illegal_opcode_locks_up(self):
+        # 0xD3 is not a valid opcode, the CPU locks up on it like real hardware
+        path = self.write_rom(make_rom(code=b"\xd3"))
+        env = rgirl_env.Env(path)
+        env.step(0)
+        report = env.diagnostics()
+        self.assertEqual(report["last_illegal_opcode"], 0xD3)
+        self.assertFalse(report["healthy"])
+        self.assertEqual(env.cpu_registers()["pc"], 0x101)
+
+
+if __name__ == "__main__":
+    unittest.main()
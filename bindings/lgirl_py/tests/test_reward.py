@@ -0,0 +1,78 @@
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase
+
+MONEY = 0xD573  # 3 bytes BCD, most significant first
+BADGES = 0xD57C
+
+
+class RewardTest(RomTestCase):
+    def setUp(self):
+        super().setUp()
+        self.env = rgirl_env.Env(self.write_rom())
+
+    def set_money(self, bcd):
+        for i, b in enumerate(bcd):
+            self.env.inject_fault(MONEY + i, b)
+
+    def test_reward_from_money_and_badges(self):
+        self.env.set_reward_weights({"money": 1.0, "badges": 5.0})
+        self.set_money(b"\x00\x00\x00")
+        self.env.inject_fault(BADGES, 0)
+        self.env.step(0)
+        self.assertEqual(self.env.step(0)[1], 0.0)
+
+        self.set_money(b"\x00\x01\x50")
+        self.env.inject_fault(BADGES, 0b1)
+        _, reward, done = self.env.step(0)
+        self.assertEqual(reward, 150.0 + 5.0)
+        self.assertFalse(done)
+
+        self.set_money(b"\x00\x00\x50")
+        self.assertEqual(self.env.step(0)[1], -100.0)
+
+    def test_reset_starts_a_new_baseline(self):
+        self.env.set_reward_weights({"money": 1.0})
+        self.set_money(b"\x00\x00\x00")
+        self.env.step(0)
+        self.env.set_reset_anchor()
+        self.set_money(b"\x00\x05\x00")
+        self.assertEqual(self.env.step(0)[1], 500.0)
+        # Going back to no money is the reset, not a loss in the new episode
+        self.env.reset()
+        self.assertEqual(self.env.step(0)[1], 0.0)
+
+    def test_state_changes_start_a_new_baseline(self):
+        self.env.set_reward_weights({"money": 1.0})
+        self.set_money(b"\x00\x00\x00")
+        self.env.step(0)
+        poor = self.env.snapshot()
+        self.set_money(b"\x00\x05\x00")
+        self.assertEqual(self.env.step(0)[1], 500.0)
+        rich = self.env.snapshot()
+        self.env.restore(poor)
+        self.assertEqual(self.env.step(0)[1], 0.0)
+
+        back_to_poor = self.env.state_delta(rich)
+        self.set_money(b"\x00\x05\x00")
+        self.assertEqual(self.env.step(0)[1], 500.0)
+        self.env.apply_delta(rich, back_to_poor)
+        self.assertEqual(self.env.step(0)[1], 0.0)
+
+        self.env.run_episode([0, 0])
+        self.assertEqual(self.env.step(0)[1], 0.0)
+
+    def test_weights(self):
+        self.assertEqual(self.env.reward_weights()["badges"], 10.0)
+        self.env.set_reward_weights({"enemy_hp": 2.0}, done_on_empty_party=False)
+        weights = self.env.reward_weights()
+        self.assertEqual(weights["money"], 0.0)
+        self.assertEqual(weights["enemy_hp"], 2.0)
+        self.assertFalse(weights["done_on_empty_party"])
+        with self.assertRaises(ValueError):
+            self.env.set_reward_weights({"steps": 1.0})
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,112 @@
+import pickle
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase, make_rom
+
+
+class EnvStateTest(RomTestCase):
+    def test_pickled_state_restores_in_fresh_env(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        for _ in range(5):
+            env.step(0)
+        state = pickle.loads(pickle.dumps(env.get_state()))
+        self.assertIsInstance(state, rgirl_env.EnvState)
+
+        other = rgirl_env.Env(path)
+        other.set_state(state)
+        self.assertEqual(other.get_mirror(), env.get_mirror())
+        for _ in range(3):
+            self.assertEqual(other.step(0), env.step(0))
+
+    def test_save_and_load_state_bytes(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        env.step(0)
+        data = env.save_state()
+        self.assertIsInstance(data, bytes)
+        before = [env.step(0)[0] for _ in range(3)]
+        env.load_state(data)
+        after = [env.step(0)[0] for _ in range(3)]
+        self.assertEqual(after, before)
+        with self.assertRaises(ValueError):
+            env.load_state(b"not a state")
+        with self.assertRaises(ValueError):
+            env.set_state(rgirl_env.EnvState(b"not a state"))
+
+    def test_same_seed_same_wram(self):
+        path = self.write_rom()
+        a = rgirl_env.Env(path, seed=1234)
+        b = rgirl_env.Env(path, seed=1234)
+        self.assertEqual(a.save_state(), b.save_state())
+        self.assertEqual(
+            rgirl_env.Env(path).save_state(), rgirl_env.Env(path, seed=42).save_state())
+        self.assertNotEqual(rgirl_env.Env(path, seed=7).save_state(), a.save_state())
+
+    def test_zero_wram(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path, zero_wram=True)
+        self.assertEqual(env.read_memory(0xC000, 0x2000), bytes(0x2000))
+        self.assertNotEqual(rgirl_env.Env(path).read_memory(0xC000, 0x2000), bytes(0x2000))
+        env.write_memory(0xC000, b"\x55")
+        env.reset()
+        self.assertEqual(env.read_memory(0xC000, 0x2000), bytes(0x2000))
+        with self.assertRaises(ValueError):
+            rgirl_env.Env(path, seed=1, zero_wram=True)
+
+    def test_read_and_write_memory(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        data = bytes(range(32))
+        env.write_memory(0xC100, data)
+        self.assertEqual(env.read_memory(0xC100, 32), data)
+        # Echo RAM
+        self.assertEqual(env.read_memory(0xE100, 32), data)
+        self.assertEqual(len(env.read_memory(0xFFF0, 16)), 16)
+        with self.assertRaises(ValueError):
+            env.read_memory(0xFFF0, 17)
+        with self.assertRaises(ValueError):
+            env.write_memory(0xFFFF, b"\x00\x00")
+
+    def test_cheats(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        env.add_cheat("016300C0")
+        env.step(0)
+        self.assertEqual(env.read_bcd(0xC000, 1), b"\x63")
+        with self.assertRaises(ValueError):
+            env.add_cheat("not a code")
+        env.clear_cheats()
+
+    def test_cpu_registers(self):
+        path = self.write_rom(make_rom(code=b"\xf3\x76"))  # DI; HALT
+        env = rgirl_env.Env(path)
+        env.step(0)
+        regs = env.cpu_registers()
+        self.assertEqual(regs["pc"], 0x102)
+        self.assertTrue(regs["halted"])
+        self.assertFalse(regs["ime"])
+        self.assertEqual(regs["zf"], bool(regs["f"] & 0x80))
+
+    def test_expose_debug_lengthens_mirror(self):
+        path = self.write_rom()
+        visible, _, _ = rgirl_env.Env(path).step(0)
+        full, _, _ = rgirl_env.Env(path, expose_debug=True).step(0)
+        self.assertEqual(len(visible), 0x58)
+        self.assertEqual(len(full), rgirl_env.mirror_size())
+
+    def test_custom_mirror_spec(self):
+        path = self.write_rom()
+        env = rgirl_env.Env(path)
+        env.set_mirror_spec(8, [(4, 0xC000, 2, "be"), (6, 0xC010, 1, "le")])
+        mirror, _, _ = env.step(0)
+        self.assertEqual(len(mirror), 8)
+        with self.assertRaises(ValueError):
+            env.set_mirror_spec(8, [(7, 0xC000, 2, "le")])
+        with self.assertRaises(ValueError):
+            env.set_mirror_spec(8, [(4, 0xC000, 1, "utf8")])
+
+
+if __name__ == "__main__":
+    unittest.main()
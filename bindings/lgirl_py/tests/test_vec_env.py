@@ -0,0 +1,58 @@
+import unittest
+
+import rgirl_env
+from helpers import RomTestCase
+
+
+def frame_counter(mirror):
+    return int.from_bytes(mirror[0:4], "little")
+
+
+class VecEnvTest(RomTestCase):
+    def setUp(self):
+        super().setUp()
+        self.path = self.write_rom()
+
+    def test_step_batch(self):
+        envs = rgirl_env.VecEnv(self.path, 8)
+        self.assertEqual(envs.num_envs(), 8)
+        mirrors, rewards, dones = envs.step_batch([0] * 8)
+        self.assertEqual((len(mirrors), len(rewards), len(dones)), (8, 8, 8))
+        self.assertEqual(len(set(mirrors)), 1)
+        again, _, _ = envs.step_batch([0] * 8)
+        self.assertEqual([frame_counter(m) for m in again], [frame_counter(mirrors[0]) + 1] * 8)
+        with self.assertRaises(ValueError):
+            envs.step_batch([0] * 7)
+        self.assertEqual(len(envs.reset_batch()), 8)
+
+    def test_reset_done_resets_only_done_envs(self):
+        envs = rgirl_env.VecEnv(self.path, 3)
+        self.assertEqual(len(envs), 3)
+        envs.step([0, 0, 0])
+        envs.set_reset_anchor()
+        anchor = frame_counter(envs.step([0, 0, 0])[0][0]) - 1
+        for _ in range(3):
+            envs.step([0, 0, 0])
+
+        envs.mark_done(1)
+        self.assertEqual(envs.done(), [False, True, False])
+        indices, observations = envs.reset_done()
+        self.assertEqual(indices, [1])
+        self.assertEqual(len(observations), 1)
+        self.assertEqual(frame_counter(observations[0]), anchor)
+        self.assertEqual(envs.done(), [False, False, False])
+
+        counters = [frame_counter(obs) for obs, _, _ in envs.step([0, 0, 0])]
+        self.assertEqual(counters, [anchor + 5, anchor + 1, anchor + 5])
+        self.assertEqual(envs.reset_done(), ([], []))
+
+    def test_step_needs_one_action_per_env(self):
+        envs = rgirl_env.VecEnv(self.path, 2)
+        with self.assertRaises(ValueError):
+            envs.step([0])
+        with self.assertRaises(IndexError):
+            envs.mark_done(2)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,159 @@
+use crate::StrResult;
+use serde::{Deserialize, Serialize};
+
+/// A cheat code, see `Cheat::parse`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Cheat {
+    /// Replaces the ROM byte at `address` with `value`, but only where the ROM holds
+    /// `compare`, if given, so other banks mapped at the same address stay untouched.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Keeps `value` at the RAM `address`: writes there store `value` instead. The bank byte
+    /// is kept but not used.
+    GameShark { bank: u8, address: u16, value: u8 },
+}
+
+fn hex_digits(code: &str) -> StrResult<Vec<u8>> {
+    code.chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or("Cheat code is not hexadecimal")
+}
+
+impl Cheat {
+    /// Parse a Game Genie code, `ABC-DEF` or `ABC-DEF-GHI`, or an 8 digit GameShark code,
+    /// `BBVVLLHH`: bank, value and the address low byte first. Dashes are optional.
+    pub fn parse(code: &str) -> StrResult<Cheat> {
+        let d = hex_digits(code.trim())?;
+        match d.len() {
+            6 | 9 => {
+                // The high nibble of the address is stored inverted
+                let address = ((d[5] ^ 0xF) as u16) << 12
+                    | (d[2] as u16) << 8
+                    | (d[3] as u16) << 4
+                    | d[4] as u16;
+                if address >= 0x8000 {
+                    return Err("Game Genie code does not patch ROM");
+                }
+                let compare = match d.len() {
+                    9 => Some(((d[6] << 4) | d[8]).rotate_right(2) ^ 0xBA),
+                    _ => None,
+                };
+                Ok(Cheat::GameGenie {
+                    address,
+                    value: (d[0] << 4) | d[1],
+                    compare,
+                })
+            }
+            8 => {
+                let byte = |i: usize| (d[i] << 4) | d[i + 1];
+                let address = (byte(6) as u16) << 8 | byte(4) as u16;
+                if address < 0x8000 {
+                    return Err("GameShark code does not poke RAM");
+                }
+                Ok(Cheat::GameShark {
+                    bank: byte(0),
+                    address,
+                    value: byte(2),
+                })
+            }
+            _ => Err("Cheat code has the wrong length"),
+        }
+    }
+}
+
+/// `value` read from the ROM at `address`, as the Game Genie codes in `cheats` patch it.
+pub fn patch_rom(cheats: &[Cheat], address: u16, value: u8) -> u8 {
+    for cheat in cheats {
+        if let Cheat::GameGenie {
+            address: a,
+            value: v,
+            compare,
+        } = *cheat
+        {
+            if a == address && compare.unwrap_or(value) == value {
+                return v;
+            }
+        }
+    }
+    value
+}
+
+/// The value to store when `value` is written to `address`, as forced by GameShark codes.
+pub fn force_ram(cheats: &[Cheat], address: u16, value: u8) -> u8 {
+    for cheat in cheats {
+        if let Cheat::GameShark {
+            address: a,
+            value: v,
+            ..
+        } = *cheat
+        {
+            if a == address {
+                return v;
+            }
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::{force_ram, patch_rom, Cheat};
+
+    #[test]
+    fn parse_game_genie() {
+        // Compare byte: 0xC9 rotated right by 2 is 0x72, XOR 0xBA
+        assert_eq!(
+            Cheat::parse("00A-17B-C49"),
+            Ok(Cheat::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+        assert_eq!(
+            Cheat::parse("3ef1f9"),
+            Ok(Cheat::GameGenie {
+                address: 0x6F1F,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        // Address 0xCA17 is not in ROM
+        assert!(Cheat::parse("00A-173").is_err());
+        assert!(Cheat::parse("00A-17B-C").is_err());
+        assert!(Cheat::parse("00A-17B-C4Z").is_err());
+    }
+
+    #[test]
+    fn parse_game_shark() {
+        assert_eq!(
+            Cheat::parse("01FF20D0"),
+            Ok(Cheat::GameShark {
+                bank: 0x01,
+                address: 0xD020,
+                value: 0xFF,
+            })
+        );
+        assert!(Cheat::parse("01FF2040").is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let cheats = [
+            Cheat::parse("00A-17B-C49").unwrap(),
+            Cheat::parse("3ef1f9").unwrap(),
+            Cheat::parse("016320D0").unwrap(),
+        ];
+        assert_eq!(patch_rom(&cheats, 0x4A17, 0xC8), 0x00);
+        assert_eq!(patch_rom(&cheats, 0x4A17, 0x12), 0x12);
+        assert_eq!(patch_rom(&cheats, 0x6F1F, 0x12), 0x3E);
+        assert_eq!(patch_rom(&cheats, 0x0100, 0x12), 0x12);
+        assert_eq!(force_ram(&cheats, 0xD020, 0x05), 0x63);
+        assert_eq!(force_ram(&cheats, 0xD021, 0x05), 0x05);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::mbc;
 use crate::mmu::MMU;
 use crate::register::CpuFlag::{C, H, N, Z};
-use crate::register::Registers;
+use crate::register::{CpuRegs, Registers};
 use crate::serial::SerialCallback;
 use crate::StrResult;
 use serde::{Deserialize, Serialize};
@@ -18,6 +18,21 @@ pub struct CPU {
     ime: bool,
     setdi: u32,
     setei: u32,
+    /// The most recently executed instruction and its address.
+    #[serde(default)]
+    last_opcode: u8,
+    #[serde(default)]
+    last_pc: u16,
+    /// STOP ran without a speed switch and no button was pressed since. Real hardware would
+    /// sleep until then; here execution goes on.
+    #[serde(default)]
+    stopped: bool,
+    /// The last instruction jumped to its own address.
+    #[serde(default)]
+    looping: bool,
+    /// The invalid opcode the CPU locked up on.
+    #[serde(default)]
+    last_illegal_opcode: Option<u8>,
 }
 
 impl CPU {
@@ -34,6 +49,11 @@ impl CPU {
             ime: true,
             setdi: 0,
             setei: 0,
+            last_opcode: 0,
+            last_pc: 0,
+            stopped: false,
+            looping: false,
+            last_illegal_opcode: None,
             mmu: cpu_mmu,
         })
     }
@@ -51,35 +71,30 @@ impl CPU {
             ime: true,
             setdi: 0,
             setei: 0,
+            last_opcode: 0,
+            last_pc: 0,
+            stopped: false,
+            looping: false,
+            last_illegal_opcode: None,
             mmu: cpu_mmu,
         })
     }
 
-        /// Reset CPU and subcomponents to a known power-on state.
-    /// This should make the machine behave like a freshly powered-on GameBoy (not a soft reset).
+    /// Return to the state right after the boot ROM: the registers it leaves behind for the
+    /// current mode and a reset MMU, see `MMU::reset`. The cartridge keeps its RAM.
     pub fn reset(&mut self) {
-        // Reset registers (example names; adapt to your CPU register fields)
-        self.regs = Default::default(); // if you have a default for registers
-        self.sp = 0xFFFE; // example SP reset value; adjust as needed
-        self.pc = 0x0100; // typical Game Boy entry point
-
-        // Reset MMU — restore RAM and IO reset values
-        self.mmu.reset(); // implement MMU::reset to set RAM/IO to initial state
-
-        // Reset GPU/LCD state
-        self.mmu.gpu = crate::gpu::GPU::new(); // or GPU::new_cgb() depending on mode
-
-        // Reset timers, serial, interrupt flags
-        self.mmu.timer_reset(); // implement to zero timers, divider, etc.
-
-        // Reset sound
-        self.mmu.sound = None; // off by default; let higher-level reattach audio
-
-        // Clear any internal CPU state (halt/stop flags)
+        self.reg = Registers::new(self.mmu.gbmode);
         self.halted = false;
+        self.halt_bug = false;
+        self.ime = true;
+        self.setdi = 0;
+        self.setei = 0;
+        self.last_opcode = 0;
+        self.last_pc = 0;
         self.stopped = false;
-
-        // Reinitialize other peripherals as required
+        self.looping = false;
+        self.last_illegal_opcode = None;
+        self.mmu.reset();
     }
 
     pub fn do_cycle(&mut self) -> u32 {
@@ -88,6 +103,16 @@ impl CPU {
     }
 
     fn docycle(&mut self) -> u32 {
+        // Like real hardware, an invalid opcode locks the CPU up until a reset
+        if self.last_illegal_opcode.is_some() {
+            return 1;
+        }
+        if self.mmu.keypad.any_pressed() {
+            self.stopped = false;
+        }
+        if self.mmu.speed_switching() {
+            return 1;
+        }
         self.updateime();
         match self.handleinterrupt() {
             0 => {}
@@ -98,7 +123,9 @@ impl CPU {
             // Emulate a noop instruction
             1
         } else {
-            self.call()
+            let ticks = self.call();
+            self.looping = self.reg.pc == self.last_pc;
+            ticks
         }
     }
 
@@ -112,6 +139,14 @@ impl CPU {
         b
     }
 
+    /// 16-bit increments and decrements of a register pair pointing into OAM corrupt it
+    /// on the DMG
+    fn oam_bug_check(&mut self, address: u16) {
+        if (0xFE00..=0xFEFF).contains(&address) {
+            self.mmu.gpu.oam_bug_write();
+        }
+    }
+
     fn fetchword(&mut self) -> u16 {
         let w = self.mmu.rw(self.reg.pc);
         self.reg.pc += 2;
@@ -177,7 +212,9 @@ impl CPU {
     }
 
     fn call(&mut self) -> u32 {
+        self.last_pc = self.reg.pc;
         let opcode = self.fetchbyte();
+        self.last_opcode = opcode;
         match opcode {
             0x00 => 1,
             0x01 => {
@@ -190,6 +227,7 @@ impl CPU {
                 2
             }
             0x03 => {
+                self.oam_bug_check(self.reg.bc());
                 self.reg.setbc(self.reg.bc().wrapping_add(1));
                 2
             }
@@ -224,6 +262,7 @@ impl CPU {
                 2
             }
             0x0B => {
+                self.oam_bug_check(self.reg.bc());
                 self.reg.setbc(self.reg.bc().wrapping_sub(1));
                 2
             }
@@ -245,7 +284,9 @@ impl CPU {
                 1
             }
             0x10 => {
-                self.mmu.switch_speed();
+                if !self.mmu.switch_speed() {
+                    self.stopped = true;
+                }
                 1
             } // STOP
             0x11 => {
@@ -258,6 +299,7 @@ impl CPU {
                 2
             }
             0x13 => {
+                self.oam_bug_check(self.reg.de());
                 self.reg.setde(self.reg.de().wrapping_add(1));
                 2
             }
@@ -291,6 +333,7 @@ impl CPU {
                 2
             }
             0x1B => {
+                self.oam_bug_check(self.reg.de());
                 self.reg.setde(self.reg.de().wrapping_sub(1));
                 2
             }
@@ -330,6 +373,7 @@ impl CPU {
                 2
             }
             0x23 => {
+                self.oam_bug_check(self.reg.hl());
                 let v = self.reg.hl().wrapping_add(1);
                 self.reg.sethl(v);
                 2
@@ -369,6 +413,7 @@ impl CPU {
                 2
             }
             0x2B => {
+                self.oam_bug_check(self.reg.hl());
                 let v = self.reg.hl().wrapping_sub(1);
                 self.reg.sethl(v);
                 2
@@ -409,6 +454,7 @@ impl CPU {
                 2
             }
             0x33 => {
+                self.oam_bug_check(self.reg.sp);
                 self.reg.sp = self.reg.sp.wrapping_add(1);
                 2
             }
@@ -455,6 +501,7 @@ impl CPU {
                 2
             }
             0x3B => {
+                self.oam_bug_check(self.reg.sp);
                 self.reg.sp = self.reg.sp.wrapping_sub(1);
                 2
             }
@@ -676,8 +723,15 @@ impl CPU {
                 2
             }
             0x76 => {
-                self.halted = true;
-                self.halt_bug = self.mmu.intf & self.mmu.inte & 0x1F != 0;
+                if self.mmu.intf & self.mmu.inte & 0x1F == 0 {
+                    self.halted = true;
+                } else if self.setei > 0 {
+                    // Right after EI the interrupt is handled next and returns to the HALT
+                    self.reg.pc = self.reg.pc.wrapping_sub(1);
+                } else if !self.ime {
+                    // HALT bug: no halt, and the next byte is read twice
+                    self.halt_bug = true;
+                }
                 1
             }
             0x77 => {
@@ -1273,7 +1327,10 @@ impl CPU {
                 self.reg.pc = 0x38;
                 4
             }
-            other => panic!("Instruction {:2X} is not implemented", other),
+            other => {
+                self.last_illegal_opcode = Some(other);
+                1
+            }
         }
     }
 
@@ -2561,6 +2618,46 @@ impl CPU {
         self.reg.pc = ((self.reg.pc as u32 as i32) + (n as i32)) as u16;
     }
 
+    pub fn registers(&self) -> CpuRegs {
+        CpuRegs {
+            ime: self.ime,
+            halted: self.halted,
+            ..self.reg.dump()
+        }
+    }
+
+    /// The opcode of the last executed instruction, 0xCB for all CB-prefixed ones.
+    /// Interrupt dispatch and halted cycles do not count as instructions.
+    pub fn last_opcode(&self) -> u8 {
+        self.last_opcode
+    }
+
+    /// The address of the last executed instruction.
+    pub fn last_pc(&self) -> u16 {
+        self.last_pc
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether STOP was executed without a speed switch and no button was pressed since. The
+    /// CPU keeps running, this only tells that the game wants to sleep until a button press.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Whether the CPU spins on an instruction jumping to itself, with no interrupt enabled
+    /// that could get it out.
+    pub fn is_looping(&self) -> bool {
+        self.looping && !(self.ime && self.mmu.inte & 0x1F != 0)
+    }
+
+    /// The invalid opcode the CPU locked up on, if any. Only a reset gets it going again.
+    pub fn last_illegal_opcode(&self) -> Option<u8> {
+        self.last_illegal_opcode
+    }
+
     pub fn read_byte(&mut self, address: u16) -> u8 {
         self.mmu.rb(address)
     }
@@ -2580,6 +2677,7 @@ impl CPU {
 mod test {
     use super::CPU;
     use crate::mbc;
+    use crate::testrom;
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use std::sync::{Arc, Mutex};
 
@@ -2621,6 +2719,80 @@ mod test {
         }
     }
 
+    fn run(rom: Vec<u8>, instructions: usize) -> CPU {
+        let mut c = CPU::new(mbc::get_mbc(rom, false).unwrap(), None).unwrap();
+        for _ in 0..instructions {
+            c.do_cycle();
+        }
+        c
+    }
+
+    #[test]
+    fn reset_restores_boot_state() {
+        let rom = testrom::rom(&[
+            0x31, 0x00, 0xD0, // LD SP, 0xD000
+            0x21, 0x34, 0x12, // LD HL, 0x1234
+            0xF3, // DI
+            0x3E, 0x05, // LD A, 5
+            0xE0, 0x05, // LDH (TIMA), A
+            0xE0, 0xFF, // LDH (IE), A
+            0x76, // HALT
+        ]);
+        let mut c = run(rom, 7);
+        assert!(c.is_halted());
+        c.reset();
+
+        assert_eq!(c.reg.pc, 0x0100);
+        assert_eq!(c.reg.sp, 0xFFFE);
+        assert_eq!(c.reg.af(), 0x01B0);
+        assert_eq!(c.reg.hl(), 0x014D);
+        assert!(c.ime);
+        assert!(!c.is_halted());
+        assert_eq!(c.mmu.rb(0xFF05), 0);
+        assert_eq!(c.mmu.inte, 0);
+        assert_eq!(c.mmu.rb(0xFF40), 0x91);
+    }
+
+    #[test]
+    fn halt_bug_with_ime_off() {
+        let rom = testrom::rom(&[
+            0xF3, // DI
+            0x3E, 0x01, // LD A, 1
+            0xE0, 0xFF, // LDH (IE), A
+            0xE0, 0x0F, // LDH (IF), A
+            0x06, 0x00, // LD B, 0
+            0x76, // HALT
+            0x04, // INC B, run twice
+            0x18, 0xFE, // JR -2
+        ]);
+        let c = run(rom, 10);
+        assert_eq!(c.reg.b, 2);
+        assert!(!c.is_halted());
+        assert_eq!(c.reg.pc, 0x010B);
+    }
+
+    #[test]
+    fn halt_after_ei_with_interrupt_pending() {
+        let mut rom = testrom::rom(&[
+            0xF3, // DI
+            0x3E, 0x01, // LD A, 1
+            0xE0, 0x0F, // LDH (IF), A
+            0xE0, 0xFF, // LDH (IE), A
+            0x0E, 0x00, // LD C, 0
+            0xFB, // EI
+            0x76, // HALT
+            0x04, // INC B
+            0x18, 0xFE, // JR -2
+        ]);
+        rom[0x40] = 0x0C; // INC C
+        rom[0x41] = 0xD9; // RETI
+        let c = run(rom, 12);
+        // The handler ran once and returned to the HALT, which now waits
+        assert_eq!(c.reg.c, 1);
+        assert!(c.is_halted());
+        assert_eq!(c.reg.pc, 0x010B);
+    }
+
     #[test]
     fn cpu_instrs_classic() {
         let mut sum_classic = 0_u32;
@@ -0,0 +1,283 @@
+use crate::StrResult;
+use ciborium::Value;
+use std::convert::TryFrom;
+
+/// Tags marking how a changed part of the state is stored in a delta. They are from the
+/// unassigned range, as ciborium gives the standard ones (e.g. bignums) special treatment.
+const TAG_REPLACE: u64 = 60000;
+const TAG_XOR: u64 = 60001;
+
+/// Runs separated by fewer equal bytes than this are merged, as a new run costs at least two
+/// header bytes.
+const MIN_GAP: usize = 3;
+
+fn base_at(base: &[u8], i: usize) -> u8 {
+    base.get(i).copied().unwrap_or(0)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> StrResult<usize> {
+    let mut v = 0usize;
+    let mut shift = 0;
+    loop {
+        let b = *data.get(*pos).ok_or("State delta is truncated")?;
+        *pos += 1;
+        if shift >= usize::BITS {
+            return Err("State delta is corrupt");
+        }
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return Ok(v);
+        }
+        shift += 7;
+    }
+}
+
+/// Encode `current` as a difference from `base`. The result starts with the length of
+/// `current`, followed by runs of `(skip, count, bytes)`: `skip` bytes are equal to the base
+/// and the next `count` bytes are `current` XORed with the base (zero past its end).
+/// All numbers are LEB128 encoded.
+fn xor_encode(base: &[u8], current: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, current.len());
+    let differs = |i: usize| current[i] != base_at(base, i);
+
+    let mut pos = 0;
+    while pos < current.len() {
+        let start = match (pos..current.len()).find(|&i| differs(i)) {
+            Some(i) => i,
+            None => break,
+        };
+        let mut end = start + 1;
+        let mut gap = 0;
+        let mut i = end;
+        while i < current.len() && gap < MIN_GAP {
+            if differs(i) {
+                end = i + 1;
+                gap = 0;
+            } else {
+                gap += 1;
+            }
+            i += 1;
+        }
+        write_varint(&mut out, start - pos);
+        write_varint(&mut out, end - start);
+        out.extend((start..end).map(|i| current[i] ^ base_at(base, i)));
+        pos = end;
+    }
+    out
+}
+
+fn xor_decode(base: &[u8], delta: &[u8]) -> StrResult<Vec<u8>> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos)?;
+    let mut out: Vec<u8> = (0..len).map(|i| base_at(base, i)).collect();
+
+    let mut offset = 0;
+    while pos < delta.len() {
+        offset += read_varint(delta, &mut pos)?;
+        let count = read_varint(delta, &mut pos)?;
+        let bytes = delta
+            .get(pos..pos + count)
+            .ok_or("State delta is truncated")?;
+        let target = out
+            .get_mut(offset..offset + count)
+            .ok_or("State delta is corrupt")?;
+        for (t, b) in target.iter_mut().zip(bytes) {
+            *t ^= b;
+        }
+        pos += count;
+        offset += count;
+    }
+    Ok(out)
+}
+
+/// The contents of an array holding only bytes, which is how serde encodes `[u8; N]`.
+fn as_bytes(items: &[Value]) -> Option<Vec<u8>> {
+    items
+        .iter()
+        .map(|v| v.as_integer().and_then(|i| u8::try_from(i).ok()))
+        .collect()
+}
+
+/// Describe how to get from `base` to `current`: `Null` if they are equal, an array of
+/// element differences for arrays and maps of the same shape, XORed bytes for byte buffers
+/// of the same size, or else the full new value.
+fn diff(base: &Value, current: &Value) -> Value {
+    if base == current {
+        return Value::Null;
+    }
+    let xor = |b: &[u8], c: &[u8]| Value::Tag(TAG_XOR, Box::new(Value::Bytes(xor_encode(b, c))));
+    match (base, current) {
+        (Value::Bytes(b), Value::Bytes(c)) => xor(b, c),
+        (Value::Array(b), Value::Array(c)) if b.len() == c.len() => {
+            match (as_bytes(b), as_bytes(c)) {
+                (Some(b), Some(c)) => xor(&b, &c),
+                _ => Value::Array(b.iter().zip(c).map(|(b, c)| diff(b, c)).collect()),
+            }
+        }
+        (Value::Map(b), Value::Map(c))
+            if b.len() == c.len() && b.iter().zip(c).all(|(b, c)| b.0 == c.0) =>
+        {
+            Value::Array(b.iter().zip(c).map(|(b, c)| diff(&b.1, &c.1)).collect())
+        }
+        _ => Value::Tag(TAG_REPLACE, Box::new(current.clone())),
+    }
+}
+
+fn apply(base: &Value, delta: Value) -> StrResult<Value> {
+    const CORRUPT: &str = "State delta does not match its base state";
+    match (base, delta) {
+        (_, Value::Null) => Ok(base.clone()),
+        (_, Value::Tag(TAG_REPLACE, value)) => Ok(*value),
+        (_, Value::Tag(TAG_XOR, value)) => {
+            let delta = value.as_bytes().ok_or(CORRUPT)?;
+            match base {
+                Value::Bytes(b) => Ok(Value::Bytes(xor_decode(b, delta)?)),
+                Value::Array(b) => {
+                    let bytes = as_bytes(b).ok_or(CORRUPT)?;
+                    let bytes = xor_decode(&bytes, delta)?;
+                    Ok(Value::Array(bytes.into_iter().map(Value::from).collect()))
+                }
+                _ => Err(CORRUPT),
+            }
+        }
+        (Value::Array(b), Value::Array(d)) if b.len() == d.len() => Ok(Value::Array(
+            b.iter()
+                .zip(d)
+                .map(|(b, d)| apply(b, d))
+                .collect::<StrResult<_>>()?,
+        )),
+        (Value::Map(b), Value::Array(d)) if b.len() == d.len() => Ok(Value::Map(
+            b.iter()
+                .zip(d)
+                .map(|((k, b), d)| Ok((k.clone(), apply(b, d)?)))
+                .collect::<StrResult<_>>()?,
+        )),
+        _ => Err(CORRUPT),
+    }
+}
+
+fn parse(data: &[u8]) -> Option<Value> {
+    ciborium::de::from_reader(data).ok()
+}
+
+fn to_bytes(value: &Value) -> Vec<u8> {
+    let mut data = Vec::new();
+    ciborium::into_writer(value, &mut data).unwrap();
+    data
+}
+
+/// Encode the CBOR state `current` as a difference from the CBOR state `base`. Only parts
+/// that changed are stored, large byte buffers as a sparse XOR with their old contents.
+/// If `base` cannot be decoded the delta holds all of `current`.
+pub fn encode(base: &[u8], current: &[u8]) -> Vec<u8> {
+    let base = parse(base).unwrap_or(Value::Null);
+    let current = parse(current).expect("Current state is not valid CBOR");
+    to_bytes(&diff(&base, &current))
+}
+
+/// Rebuild the state `delta` was encoded from. `base` must be the same one passed to `encode`.
+pub fn decode(base: &[u8], delta: &[u8]) -> StrResult<Vec<u8>> {
+    let base = parse(base).unwrap_or(Value::Null);
+    let delta = parse(delta).ok_or("State delta is not valid CBOR")?;
+    Ok(to_bytes(&apply(&base, delta)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, xor_decode, xor_encode};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct State {
+        counter: u32,
+        name: String,
+        ram: Vec<u8>,
+        regs: [u16; 4],
+    }
+
+    fn cbor(state: &State) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::into_writer(state, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn xor_roundtrip() {
+        let base: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let mut current = base.clone();
+        current[3] ^= 0x55;
+        current[5] = 0;
+        current[700..760].iter_mut().for_each(|b| *b = 0xAA);
+
+        let delta = xor_encode(&base, &current);
+        assert!(delta.len() < 100);
+        assert_eq!(xor_decode(&base, &delta).unwrap(), current);
+    }
+
+    #[test]
+    fn xor_length_changes() {
+        let base = vec![1u8; 64];
+        let longer = vec![1u8; 80];
+        let shorter = vec![1u8; 40];
+        assert_eq!(
+            xor_decode(&base, &xor_encode(&base, &longer)).unwrap(),
+            longer
+        );
+        assert_eq!(
+            xor_decode(&base, &xor_encode(&base, &shorter)).unwrap(),
+            shorter
+        );
+        assert_eq!(xor_encode(&base, &base).len(), 1);
+        assert!(xor_decode(&base, &[4, 0, 2, 1]).is_err());
+    }
+
+    #[test]
+    fn state_roundtrip() {
+        let base = State {
+            counter: 3,
+            name: "base".to_string(),
+            ram: (0..4096u32).map(|i| i as u8).collect(),
+            regs: [1, 2, 3, 4],
+        };
+        let mut current = base.clone();
+        // Crosses a CBOR integer size boundary, which shifts all later bytes.
+        current.counter = 300;
+        current.ram[100] = 0;
+        current.regs[2] = 0x1234;
+
+        let delta = encode(&cbor(&base), &cbor(&current));
+        assert!(delta.len() < 64);
+        let decoded = decode(&cbor(&base), &delta).unwrap();
+        let decoded: State = ciborium::de::from_reader(&decoded[..]).unwrap();
+        assert_eq!(decoded, current);
+
+        current.ram.truncate(10);
+        let delta = encode(&cbor(&base), &cbor(&current));
+        let decoded = decode(&cbor(&base), &delta).unwrap();
+        assert_eq!(decoded, cbor(&current));
+    }
+
+    #[test]
+    fn wrong_base_is_an_error() {
+        let base = State {
+            counter: 0,
+            name: String::new(),
+            ram: vec![0; 16],
+            regs: [0; 4],
+        };
+        let mut current = base.clone();
+        current.ram[0] = 1;
+        let delta = encode(&cbor(&base), &cbor(&current));
+        assert!(decode(&[], &delta).is_err());
+        assert!(decode(&cbor(&base), &delta[..delta.len() - 1]).is_err());
+    }
+}
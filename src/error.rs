@@ -0,0 +1,16 @@
+//! Messages carried by the `StrResult` errors that `Device` construction can return, so callers
+//! can tell them apart without copying the text.
+
+pub const UNSUPPORTED_MBC: &str = "Unsupported MBC type";
+pub const BAD_CHECKSUM: &str = "Cartridge checksum is invalid";
+pub const ROM_TOO_SMALL: &str = "Rom size to small";
+pub const ROM_SIZE_MISMATCH: &str = "ROM size does not match the cartridge header";
+pub const CLASSIC_MODE_UNSUPPORTED: &str = "This game does not work in Classic mode";
+pub const SAVE_READ_FAILED: &str = "Error while reading existing save file";
+pub const SAVE_LOAD_FAILED: &str = "Error loading existing save file";
+pub const CLOCK_LOAD_FAILED: &str = "Error loading existing clock file";
+pub const RAM_WRONG_LENGTH: &str = "Loaded RAM has incorrect length";
+pub const RAM_TOO_SMALL: &str = "Loaded ram is too small";
+pub const CLOCK_WRONG_SIZE: &str = "Loaded clock has the wrong size";
+pub const NO_CLOCK: &str = "This cartridge has no real-time clock";
+pub const BATTERY_RAM_SIZE_MISMATCH: &str = "Battery RAM size does not match the cartridge header";
@@ -41,9 +41,6 @@ pub struct GPU {
     palbr: u8,
     pal0r: u8,
     pal1r: u8,
-    palb: [u8; 4],
-    pal0: [u8; 4],
-    pal1: [u8; 4],
     #[serde(with = "serde_arrays")]
     vram: [u8; VRAM_SIZE],
     #[serde(with = "serde_arrays")]
@@ -64,6 +61,81 @@ pub struct GPU {
     pub vblank_start: bool,
     hblanking: bool,
     first_frame: bool,
+    #[serde(default)]
+    midframe_latching: bool,
+    #[serde(default)]
+    latched_writes: Vec<(u16, u8)>,
+    #[serde(default = "default_true")]
+    rendering_enabled: bool,
+    #[serde(default)]
+    oam_bug: bool,
+    #[serde(default = "default_true")]
+    show_bg: bool,
+    #[serde(default = "default_true")]
+    show_window: bool,
+    #[serde(default = "default_true")]
+    show_sprites: bool,
+    /// STAT enable bits (3-6) of the conditions that raised the last STAT interrupt.
+    #[serde(default)]
+    stat_source: u8,
+    /// RGB of the four shades in classic mode, lightest first.
+    #[serde(default = "default_dmg_palette")]
+    dmg_palette: [[u8; 3]; 4],
+}
+
+/// 64-bit FNV-1a, a fast hash that is the same on every platform and run.
+pub fn fnv1a_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Pixel layout of the frames handed out by the device. The GPU itself always renders RGB.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum FrameFormat {
+    #[default]
+    Rgb,
+    Bgr,
+    Rgba,
+    Bgra,
+}
+
+impl FrameFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            FrameFormat::Rgb | FrameFormat::Bgr => 3,
+            FrameFormat::Rgba | FrameFormat::Bgra => 4,
+        }
+    }
+
+    /// Convert an RGB frame to this format. Alpha is always opaque.
+    pub fn convert(self, rgb: &[u8]) -> Vec<u8> {
+        let bgr = matches!(self, FrameFormat::Bgr | FrameFormat::Bgra);
+        let alpha = self.bytes_per_pixel() == 4;
+        let mut out = Vec::with_capacity(rgb.len() / 3 * self.bytes_per_pixel());
+        for p in rgb.chunks_exact(3) {
+            if bgr {
+                out.extend_from_slice(&[p[2], p[1], p[0]]);
+            } else {
+                out.extend_from_slice(p);
+            }
+            if alpha {
+                out.push(0xFF);
+            }
+        }
+        out
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The classic mode shades: white, light grey, dark grey and black.
+pub const DEFAULT_DMG_PALETTE: [[u8; 3]; 4] = [[255; 3], [192; 3], [96; 3], [0; 3]];
+
+fn default_dmg_palette() -> [[u8; 3]; 4] {
+    DEFAULT_DMG_PALETTE
 }
 
 impl GPU {
@@ -94,9 +166,6 @@ impl GPU {
             palbr: 0,
             pal0r: 0,
             pal1r: 1,
-            palb: [0; 4],
-            pal0: [0; 4],
-            pal1: [0; 4],
             vram: [0; VRAM_SIZE],
             voam: [0; VOAM_SIZE],
             data: vec![0; SCREEN_W * SCREEN_H * 3],
@@ -114,6 +183,15 @@ impl GPU {
             vblank_start: false,
             hblanking: false,
             first_frame: false,
+            midframe_latching: false,
+            latched_writes: Vec::new(),
+            rendering_enabled: true,
+            oam_bug: false,
+            show_bg: true,
+            show_window: true,
+            show_sprites: true,
+            stat_source: 0,
+            dmg_palette: DEFAULT_DMG_PALETTE,
         }
     }
 
@@ -121,6 +199,93 @@ impl GPU {
         GPU::new()
     }
 
+    /// Return to the power-on state (LCD off, VRAM and OAM cleared), keeping the emulator
+    /// options: rendering, layer mask, OAM bug and register latching.
+    pub fn reset(&mut self) {
+        let mut gpu = GPU::new();
+        gpu.gbmode = self.gbmode;
+        gpu.midframe_latching = self.midframe_latching;
+        gpu.oam_bug = self.oam_bug;
+        gpu.show_bg = self.show_bg;
+        gpu.show_window = self.show_window;
+        gpu.show_sprites = self.show_sprites;
+        gpu.dmg_palette = self.dmg_palette;
+        if !self.rendering_enabled {
+            gpu.release_frame_buffer();
+        }
+        *self = gpu;
+    }
+
+    /// When enabled, writes to the scroll, window position and palette registers (SCY, SCX,
+    /// WY, WX, BGP, OBP0, OBP1) made while a visible scanline is being drawn (mode 2 or 3) are
+    /// held back and applied at the next scanline boundary, so the line in progress is
+    /// rendered with the old values. Reads return the old values until then as well. Disabled by default, in which case
+    /// every write takes effect immediately.
+    pub fn set_midframe_register_latching(&mut self, enabled: bool) {
+        self.midframe_latching = enabled;
+        if !enabled {
+            self.apply_latched_writes();
+        }
+    }
+
+    /// When disabled, no pixels are composed: `data` is left untouched. Mode, LY, interrupt
+    /// timing and `updated` are unaffected, so games polling STAT keep running and frames
+    /// are still paced.
+    pub fn set_rendering_enabled(&mut self, enabled: bool) {
+        self.rendering_enabled = enabled;
+        if enabled && self.data.is_empty() {
+            self.data = vec![0; SCREEN_W * SCREEN_H * 3];
+        }
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.rendering_enabled
+    }
+
+    /// Choose which layers are drawn into `data`. Pixels of a hidden background or window
+    /// stay blank (color 0 is not drawn either), hidden sprites are skipped entirely. Only the
+    /// output is affected, timing and interrupts stay the same. All layers are on by default.
+    pub fn set_layer_mask(&mut self, bg: bool, window: bool, sprites: bool) {
+        self.show_bg = bg;
+        self.show_window = window;
+        self.show_sprites = sprites;
+    }
+
+    /// Disable rendering and free the frame buffer, leaving `data` empty. Enabling rendering
+    /// again allocates a new (black) buffer.
+    pub fn release_frame_buffer(&mut self) {
+        self.rendering_enabled = false;
+        self.data = Vec::new();
+    }
+
+    /// Hash of the tile data and tile maps in both VRAM banks. Unlike a hash of the frame it
+    /// does not change on scrolling or palette changes.
+    pub fn vram_hash(&self) -> u64 {
+        fnv1a_hash(&self.vram)
+    }
+
+    /// Mean luma (BT.601) of the frame buffer, from 0 (black) to 255 (white). 0 without a
+    /// frame buffer.
+    pub fn average_brightness(&self) -> u8 {
+        let pixels = self.data.len() / 3;
+        if pixels == 0 {
+            return 0;
+        }
+        let total: u64 = self
+            .data
+            .chunks_exact(3)
+            .map(|p| 299 * p[0] as u64 + 587 * p[1] as u64 + 114 * p[2] as u64)
+            .sum();
+        (total / (1000 * pixels as u64)) as u8
+    }
+
+    fn apply_latched_writes(&mut self) {
+        let writes = std::mem::take(&mut self.latched_writes);
+        for (a, v) in writes {
+            self.write_latchable(a, v);
+        }
+    }
+
     pub fn do_cycle(&mut self, ticks: u32) {
         if !self.lcd_on {
             return;
@@ -138,6 +303,7 @@ impl GPU {
             if self.modeclock >= 456 {
                 self.modeclock -= 456;
                 self.line = (self.line + 1) % 154;
+                self.apply_latched_writes();
                 self.check_interrupt_lyc();
 
                 // This is a VBlank line
@@ -170,6 +336,7 @@ impl GPU {
     fn check_interrupt_lyc(&mut self) {
         if self.lyc_inte && self.line == self.lyc {
             self.interrupt |= 0x02;
+            self.stat_source = 0x40;
         }
     }
 
@@ -178,7 +345,9 @@ impl GPU {
 
         if match self.mode {
             0 => {
-                self.renderscan();
+                if self.rendering_enabled {
+                    self.renderscan();
+                }
                 self.hblanking = true;
                 self.m0_inte
             }
@@ -202,7 +371,82 @@ impl GPU {
             _ => false,
         } {
             self.interrupt |= 0x02;
+            self.stat_source = 0x08 << self.mode;
+        }
+    }
+
+    /// Emulate the DMG OAM corruption bug, triggered by 16-bit increments and decrements of
+    /// a register pair holding an OAM address while OAM is being scanned. Off by default:
+    /// games avoid the bug and only test ROMs depend on it.
+    pub fn set_oam_bug_accuracy(&mut self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    /// Apply the write corruption pattern to the OAM row being scanned, when the bug applies.
+    /// OAM is scanned as 20 rows of 8 bytes, one row every 4 cycles of mode 2. The first word
+    /// of the row becomes ((a ^ c) & (b ^ c)) ^ c, with a the first word of the row and b and c
+    /// the first and third word of the preceding row; the other three words are copied from
+    /// the preceding row. The first row is never corrupted.
+    pub fn oam_bug_write(&mut self) {
+        if !self.oam_bug || self.gbmode != GbMode::Classic || !self.lcd_on || self.mode != 2 {
+            return;
+        }
+        let row = (self.modeclock / 4) as usize;
+        if row == 0 || row >= 20 {
+            return;
         }
+        let cur = row * 8;
+        let prev = cur - 8;
+        for i in 0..2 {
+            let a = self.voam[cur + i];
+            let b = self.voam[prev + i];
+            let c = self.voam[prev + 4 + i];
+            self.voam[cur + i] = ((a ^ c) & (b ^ c)) ^ c;
+        }
+        for i in 2..8 {
+            self.voam[cur + i] = self.voam[prev + i];
+        }
+    }
+
+    /// Debug only: move the PPU to the start of scanline `ly`, in mode 2 (or 1 for the VBlank
+    /// lines) and raise the LYC=LY interrupt when it applies. This bypasses the normal timing
+    /// and may desync a running game.
+    pub fn set_ly(&mut self, ly: u8) {
+        self.line = ly % 154;
+        self.modeclock = 0;
+        self.mode = if self.line >= 144 { 1 } else { 2 };
+        self.check_interrupt_lyc();
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcd_on
+    }
+
+    /// The PPU mode, as in the lower bits of STAT: 0 HBlank, 1 VBlank, 2 OAM scan, 3 drawing.
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    /// The scanline being drawn (LY), 0 to 153.
+    pub fn ly(&self) -> u8 {
+        self.line
+    }
+
+    pub fn lyc(&self) -> u8 {
+        self.lyc
+    }
+
+    /// Which condition raised the last STAT interrupt, as its STAT enable bit: 0x08 HBlank,
+    /// 0x10 VBlank, 0x20 OAM scan or 0x40 LYC=LY. 0 if there was none yet.
+    pub fn stat_source(&self) -> u8 {
+        self.stat_source
+    }
+
+    /// Returns true when VBlank has been entered since the flag was last consumed by
+    /// `take_vblank`. Unlike `take_vblank` this leaves the flag set, so other consumers
+    /// still see it.
+    pub fn vblank_pending(&self) -> bool {
+        self.vblank_start
     }
 
     /// Returns true once when we have just entered VBlank; consumes the flag.
@@ -277,6 +521,50 @@ impl GPU {
         }
     }
 
+    /// Background tile index and raw 2bpp color number (before the palette is applied) at
+    /// screen pixel (`px`, `py`), taking SCX/SCY scrolling into account. The window and
+    /// sprites are ignored.
+    pub fn bg_tile_at(&self, px: u8, py: u8) -> (u8, u8) {
+        let bgx = self.scx.wrapping_add(px);
+        let bgy = self.scy.wrapping_add(py);
+        let mapaddress = self.bg_tilemap + (bgy as u16 >> 3) * 32 + (bgx as u16 >> 3);
+        let tilenr = self.rbvram0(mapaddress);
+
+        let (vram1, xflip, yflip) = if self.gbmode == GbMode::Color {
+            let flags = self.rbvram1(mapaddress);
+            (
+                flags & (1 << 3) != 0,
+                flags & (1 << 5) != 0,
+                flags & (1 << 6) != 0,
+            )
+        } else {
+            (false, false, false)
+        };
+
+        let tileaddress = self.tilebase
+            + (if self.tilebase == 0x8000 {
+                tilenr as u16
+            } else {
+                (tilenr as i8 as i16 + 128) as u16
+            }) * 16;
+        let pixely = bgy as u16 & 0x07;
+        let a0 = match yflip {
+            false => tileaddress + (pixely * 2),
+            true => tileaddress + (14 - (pixely * 2)),
+        };
+        let (b1, b2) = match vram1 {
+            false => (self.rbvram0(a0), self.rbvram0(a0 + 1)),
+            true => (self.rbvram1(a0), self.rbvram1(a0 + 1)),
+        };
+        let xbit = match xflip {
+            true => bgx & 0x07,
+            false => 7 - (bgx & 0x07),
+        };
+        let colnr = ((b1 >> xbit) & 1) | (((b2 >> xbit) & 1) << 1);
+
+        (tilenr, colnr)
+    }
+
     fn rbvram0(&self, a: u16) -> u8 {
         if a < 0x8000 || a >= 0xA000 {
             panic!("Shouldn't have used rbvram0");
@@ -310,6 +598,7 @@ impl GPU {
                     self.mode = 0;
                     self.wy_trigger = false;
                     self.first_frame = true;
+                    self.apply_latched_writes();
                     self.clear_screen();
                 }
                 if !orig_lcd_on && self.lcd_on {
@@ -323,28 +612,23 @@ impl GPU {
                 self.m1_inte = v & 0x10 == 0x10;
                 self.m0_inte = v & 0x08 == 0x08;
             }
-            0xFF42 => self.scy = v,
-            0xFF43 => self.scx = v,
             0xFF44 => {} // Read-only
             0xFF45 => {
                 self.lyc = v;
                 self.check_interrupt_lyc();
             }
             0xFF46 => panic!("0xFF46 should be handled by MMU"),
-            0xFF47 => {
-                self.palbr = v;
-                self.update_pal();
-            }
-            0xFF48 => {
-                self.pal0r = v;
-                self.update_pal();
-            }
-            0xFF49 => {
-                self.pal1r = v;
-                self.update_pal();
+            0xFF42 | 0xFF43 | 0xFF47..=0xFF49 | 0xFF4A | 0xFF4B => {
+                if self.midframe_latching
+                    && self.lcd_on
+                    && self.line < 144
+                    && (self.mode == 2 || self.mode == 3)
+                {
+                    self.latched_writes.push((a, v));
+                } else {
+                    self.write_latchable(a, v);
+                }
             }
-            0xFF4A => self.winy = v,
-            0xFF4B => self.winx = v,
             0xFF4C => {}
             0xFF4E => {}
             0xFF4F..=0xFF6B if self.gbmode != GbMode::Color => {}
@@ -393,48 +677,79 @@ impl GPU {
         }
     }
 
-    fn clear_screen(&mut self) {
-        for v in self.data.iter_mut() {
-            *v = 255;
+    /// Write one of the registers `set_midframe_register_latching` can hold back.
+    fn write_latchable(&mut self, a: u16, v: u8) {
+        match a {
+            0xFF42 => self.scy = v,
+            0xFF43 => self.scx = v,
+            0xFF47 => self.palbr = v,
+            0xFF48 => self.pal0r = v,
+            0xFF49 => self.pal1r = v,
+            0xFF4A => self.winy = v,
+            0xFF4B => self.winx = v,
+            _ => panic!("GPU does not latch writes to {:04X}", a),
         }
-        self.updated = true;
     }
 
-    fn update_pal(&mut self) {
-        for i in 0..4 {
-            self.palb[i] = GPU::get_monochrome_pal_val(self.palbr, i);
-            self.pal0[i] = GPU::get_monochrome_pal_val(self.pal0r, i);
-            self.pal1[i] = GPU::get_monochrome_pal_val(self.pal1r, i);
+    /// Show a blank (white) screen until the next frame is drawn.
+    pub fn blank_screen(&mut self) {
+        self.clear_screen();
+    }
+
+    fn clear_screen(&mut self) {
+        self.updated = true;
+        if !self.rendering_enabled {
+            return;
+        }
+        let white = self.white();
+        for p in self.data.chunks_exact_mut(3) {
+            p.copy_from_slice(&white);
         }
     }
 
-    fn get_monochrome_pal_val(value: u8, index: usize) -> u8 {
-        match (value >> 2 * index) & 0x03 {
-            0 => 255,
-            1 => 192,
-            2 => 96,
-            _ => 0,
+    /// Set the RGB colors of the four classic mode shades, lightest first. Takes effect from
+    /// the next scanline drawn; CGB games keep their own palettes.
+    pub fn set_dmg_palette(&mut self, colors: [[u8; 3]; 4]) {
+        self.dmg_palette = colors;
+    }
+
+    pub fn dmg_palette(&self) -> [[u8; 3]; 4] {
+        self.dmg_palette
+    }
+
+    /// The color of a blank screen.
+    fn white(&self) -> [u8; 3] {
+        if self.gbmode == GbMode::Color {
+            [255; 3]
+        } else {
+            self.dmg_palette[0]
         }
     }
 
+    fn get_monochrome_pal_val(&self, value: u8, index: usize) -> [u8; 3] {
+        self.dmg_palette[((value >> (2 * index)) & 0x03) as usize]
+    }
+
     fn renderscan(&mut self) {
         if self.first_frame {
             // The first frame from when lcd_on is set should not be drawn.
             return;
         }
 
+        let white = self.white();
         for x in 0..SCREEN_W {
-            self.setcolor(x, 255);
+            self.setcolor(x, white);
             self.bgprio[x] = PrioType::Normal;
         }
         self.draw_bg();
         self.draw_sprites();
     }
 
-    fn setcolor(&mut self, x: usize, color: u8) {
-        self.data[self.line as usize * SCREEN_W * 3 + x * 3 + 0] = color;
-        self.data[self.line as usize * SCREEN_W * 3 + x * 3 + 1] = color;
-        self.data[self.line as usize * SCREEN_W * 3 + x * 3 + 2] = color;
+    fn setcolor(&mut self, x: usize, color: [u8; 3]) {
+        let baseidx = self.line as usize * SCREEN_W * 3 + x * 3;
+        self.data[baseidx] = color[0];
+        self.data[baseidx + 1] = color[1];
+        self.data[baseidx + 2] = color[2];
     }
 
     fn setrgb(&mut self, x: usize, r: u8, g: u8, b: u8) {
@@ -477,6 +792,9 @@ impl GPU {
             let bgx = self.scx as u32 + x as u32;
 
             let (tilemapbase, tiley, tilex, pixely, pixelx) = if winy >= 0 && winx >= 0 {
+                if !self.show_window {
+                    continue;
+                }
                 (
                     self.win_tilemap,
                     wintiley,
@@ -485,6 +803,9 @@ impl GPU {
                     winx as u8 & 0x07,
                 )
             } else if drawbg {
+                if !self.show_bg {
+                    continue;
+                }
                 (
                     self.bg_tilemap,
                     bgtiley,
@@ -548,14 +869,14 @@ impl GPU {
                 let b = self.cbgpal[palnr][colnr][2];
                 self.setrgb(x as usize, r, g, b);
             } else {
-                let color = self.palb[colnr];
+                let color = self.get_monochrome_pal_val(self.palbr, colnr);
                 self.setcolor(x, color);
             }
         }
     }
 
     fn draw_sprites(&mut self) {
-        if !self.sprite_on {
+        if !self.sprite_on || !self.show_sprites {
             return;
         }
 
@@ -641,9 +962,9 @@ impl GPU {
                         continue 'xloop;
                     }
                     let color = if usepal1 {
-                        self.pal1[colnr]
+                        self.get_monochrome_pal_val(self.pal1r, colnr)
                     } else {
-                        self.pal0[colnr]
+                        self.get_monochrome_pal_val(self.pal0r, colnr)
                     };
                     self.setcolor((spritex + x) as usize, color);
                 }
@@ -670,3 +991,232 @@ fn cgb_sprite_order(a: &(i32, i32, u8), b: &(i32, i32, u8)) -> Ordering {
     // CGB order: only prioritize based on OAM position.
     return b.2.cmp(&a.2);
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FrameFormat, GPU, SCREEN_W};
+    use crate::gbmode::GbMode;
+
+    fn run_until_mode(gpu: &mut GPU, mode: u8) {
+        while gpu.mode != mode {
+            gpu.do_cycle(4);
+        }
+    }
+
+    fn render_with_midline_bgp_write(latching: bool) -> GPU {
+        let mut gpu = GPU::new();
+        gpu.set_midframe_register_latching(latching);
+        gpu.wb(0xFF47, 0xFC); // color 0 is white
+        gpu.wb(0xFF40, 0x91);
+
+        // Line 0 is being drawn when BGP changes color 0 to black
+        run_until_mode(&mut gpu, 3);
+        gpu.wb(0xFF47, 0xFF);
+        run_until_mode(&mut gpu, 0);
+        run_until_mode(&mut gpu, 2);
+        run_until_mode(&mut gpu, 0);
+        gpu
+    }
+
+    #[test]
+    fn vram_hash() {
+        let mut gpu = GPU::new();
+        gpu.gbmode = GbMode::Color;
+        let hash = gpu.vram_hash();
+        gpu.wb(0xFF42, 0x10);
+        gpu.wb(0xFF47, 0x1B);
+        assert_eq!(gpu.vram_hash(), hash);
+
+        gpu.wb(0x8010, 0xFF);
+        let tile_changed = gpu.vram_hash();
+        assert_ne!(tile_changed, hash);
+
+        gpu.wb(0xFF4F, 1);
+        gpu.wb(0x8010, 0xFF);
+        assert_ne!(gpu.vram_hash(), tile_changed);
+    }
+
+    #[test]
+    fn frame_format_conversion() {
+        let rgb = [1, 2, 3, 4, 5, 6];
+        assert_eq!(FrameFormat::Rgb.convert(&rgb), rgb);
+        assert_eq!(FrameFormat::Bgr.convert(&rgb), [3, 2, 1, 6, 5, 4]);
+        assert_eq!(
+            FrameFormat::Rgba.convert(&rgb),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+        assert_eq!(
+            FrameFormat::Bgra.convert(&rgb),
+            [3, 2, 1, 255, 6, 5, 4, 255]
+        );
+    }
+
+    #[test]
+    fn average_brightness() {
+        for &(bgp, brightness) in &[(0xFC, 255), (0xFF, 0)] {
+            let mut gpu = GPU::new();
+            gpu.wb(0xFF47, bgp);
+            gpu.wb(0xFF40, 0x91);
+            run_until_mode(&mut gpu, 1);
+            assert_eq!(gpu.average_brightness(), brightness);
+        }
+        let mut gpu = GPU::new();
+        gpu.release_frame_buffer();
+        assert_eq!(gpu.average_brightness(), 0);
+    }
+
+    #[test]
+    fn dmg_palette() {
+        let mut gpu = GPU::new();
+        // Tile 1 is solid color 1, in the top left corner
+        for row in 0..8 {
+            gpu.wb(0x8010 + row * 2, 0xFF);
+        }
+        gpu.wb(0x9800, 1);
+        gpu.wb(0xFF47, 0xE4);
+        gpu.wb(0xFF40, 0x91);
+        run_until_mode(&mut gpu, 1);
+        run_until_mode(&mut gpu, 2);
+        run_until_mode(&mut gpu, 1);
+        assert_eq!(gpu.data[..3], [192; 3]);
+        assert_eq!(gpu.data[8 * 3..8 * 3 + 3], [255; 3]);
+
+        let green = [[155, 188, 15], [139, 172, 15], [48, 98, 48], [15, 56, 15]];
+        gpu.set_dmg_palette(green);
+        run_until_mode(&mut gpu, 2);
+        run_until_mode(&mut gpu, 1);
+        assert_eq!(gpu.data[..3], green[1]);
+        assert_eq!(gpu.data[8 * 3..8 * 3 + 3], green[0]);
+
+        gpu.reset();
+        assert_eq!(gpu.dmg_palette(), green);
+    }
+
+    #[test]
+    fn bg_tile_at_scrolled() {
+        let mut gpu = GPU::new();
+        gpu.wb(0xFF40, 0x91);
+        // Tile 1 is solid color 1, placed at tile map position (2, 3)
+        for row in 0..8 {
+            gpu.wb(0x8010 + row * 2, 0xFF);
+        }
+        gpu.wb(0x9800 + 3 * 32 + 2, 1);
+        gpu.wb(0xFF43, 8);
+
+        assert_eq!(gpu.bg_tile_at(10, 24), (1, 1));
+        assert_eq!(gpu.bg_tile_at(18, 24), (0, 0));
+        assert_eq!(gpu.bg_tile_at(10, 16), (0, 0));
+    }
+
+    fn oam_bug_gpu(enabled: bool) -> GPU {
+        let mut gpu = GPU::new();
+        gpu.set_oam_bug_accuracy(enabled);
+        gpu.wb(0xFF40, 0x91);
+        for i in 0..0xA0 {
+            gpu.wb(0xFE00 + i, i as u8);
+        }
+        run_until_mode(&mut gpu, 2);
+        // Scanning row 3
+        gpu.modeclock = 12;
+        gpu.oam_bug_write();
+        gpu
+    }
+
+    #[test]
+    fn oam_bug_corrupts_scanned_row() {
+        let gpu = oam_bug_gpu(true);
+        let (a, b, c) = (0x18u8, 0x10u8, 0x14u8);
+        assert_eq!(gpu.rb(0xFE18), ((a ^ c) & (b ^ c)) ^ c);
+        assert_eq!(gpu.rb(0xFE19), ((0x19 ^ 0x15) & (0x11 ^ 0x15)) ^ 0x15);
+        for i in 2..8 {
+            assert_eq!(gpu.rb(0xFE18 + i), 0x10 + i as u8);
+        }
+        assert_eq!(gpu.rb(0xFE10), 0x10);
+        assert_eq!(gpu.rb(0xFE20), 0x20);
+    }
+
+    #[test]
+    fn oam_bug_disabled() {
+        let gpu = oam_bug_gpu(false);
+        for i in 0..0xA0 {
+            assert_eq!(gpu.rb(0xFE00 + i), i as u8);
+        }
+    }
+
+    fn render_with_sprite(lcdc: u8, sprites_layer: bool) -> Vec<u8> {
+        let mut gpu = GPU::new();
+        gpu.set_layer_mask(true, true, sprites_layer);
+        gpu.wb(0xFF47, 0xE4);
+        gpu.wb(0xFF48, 0xE4);
+        // Background tile 0 has a vertical stripe, tile 1 (the sprite) is solid color 3
+        for row in 0..8 {
+            gpu.wb(0x8000 + row * 2, 0x81);
+            gpu.wb(0x8010 + row * 2, 0xFF);
+            gpu.wb(0x8011 + row * 2, 0xFF);
+        }
+        gpu.wb(0xFE00, 16 + 10);
+        gpu.wb(0xFE01, 8 + 20);
+        gpu.wb(0xFE02, 1);
+        gpu.wb(0xFF40, lcdc);
+        run_until_mode(&mut gpu, 1);
+        gpu.data
+    }
+
+    #[test]
+    fn layer_mask_hides_sprites() {
+        let with_sprites = render_with_sprite(0x93, true);
+        let masked = render_with_sprite(0x93, false);
+        let background_only = render_with_sprite(0x91, true);
+        assert_eq!(masked, background_only);
+        assert_ne!(with_sprites, background_only);
+    }
+
+    #[test]
+    fn set_ly_triggers_coincidence() {
+        let mut gpu = GPU::new();
+        gpu.wb(0xFF40, 0x91);
+        gpu.wb(0xFF41, 0x40);
+        gpu.wb(0xFF45, 100);
+        gpu.interrupt = 0;
+
+        gpu.set_ly(99);
+        assert_eq!(gpu.interrupt, 0);
+        assert_eq!(gpu.rb(0xFF41) & 0x04, 0);
+
+        gpu.set_ly(100);
+        assert_eq!(gpu.rb(0xFF44), 100);
+        assert_eq!(gpu.rb(0xFF41) & 0x07, 0x04 | 2);
+        assert_eq!(gpu.interrupt, 0x02);
+    }
+
+    #[test]
+    fn vblank_pending_does_not_consume() {
+        let mut gpu = GPU::new();
+        gpu.wb(0xFF40, 0x91);
+        run_until_mode(&mut gpu, 1);
+        assert!(gpu.vblank_pending());
+        assert!(gpu.vblank_pending());
+        assert!(gpu.take_vblank());
+        assert!(!gpu.vblank_pending());
+    }
+
+    #[test]
+    fn midline_bgp_write_immediate() {
+        let gpu = render_with_midline_bgp_write(false);
+        assert_eq!(gpu.rb(0xFF47), 0xFF);
+        assert_eq!(gpu.data[0], 0);
+        assert_eq!(gpu.data[SCREEN_W * 3], 0);
+    }
+
+    #[test]
+    fn midline_bgp_write_latched() {
+        let gpu = render_with_midline_bgp_write(true);
+        assert_eq!(gpu.rb(0xFF47), 0xFF);
+        assert_eq!(gpu.data[0], 255, "line 0 should use the old palette");
+        assert_eq!(
+            gpu.data[SCREEN_W * 3],
+            0,
+            "line 1 should use the new palette"
+        );
+    }
+}
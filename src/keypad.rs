@@ -5,9 +5,16 @@ pub struct Keypad {
     row0: u8,
     row1: u8,
     data: u8,
+    /// Key lines as of the last update, row1 in the high nibble, for edge detection.
+    #[serde(default = "released_lines")]
+    lines: u8,
     pub interrupt: u8,
 }
 
+fn released_lines() -> u8 {
+    0xFF
+}
+
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum KeypadKey {
     Right,
@@ -26,12 +33,14 @@ impl Keypad {
             row0: 0x0F,
             row1: 0x0F,
             data: 0xFF,
+            lines: 0xFF,
             interrupt: 0,
         }
     }
 
+    /// Bits 6 and 7 are not connected and always read as 1.
     pub fn rb(&self) -> u8 {
-        self.data
+        self.data | 0xC0
     }
 
     pub fn wb(&mut self, value: u8) {
@@ -65,8 +74,9 @@ impl Keypad {
         self.update();
     }
 
+    /// Refresh the readable bits, and raise the interrupt on a 1 to 0 edge of any key line,
+    /// whichever row is selected.
     fn update(&mut self) {
-        let old_values = self.data & 0xF;
         let mut new_values = 0xF;
 
         if self.data & 0x10 == 0x00 {
@@ -76,37 +86,54 @@ impl Keypad {
             new_values &= self.row1;
         }
 
-        if old_values == 0xF && new_values != 0xF {
+        let lines = (self.row1 << 4) | self.row0;
+        if self.lines & !lines != 0 {
             self.interrupt |= 0x10;
         }
+        self.lines = lines;
 
         self.data = (self.data & 0xF0) | new_values;
     }
 
-    pub fn keydown(&mut self, key: KeypadKey) {
+    /// Whether any key is held, whichever row the game selected.
+    pub fn any_pressed(&self) -> bool {
+        self.row0 & self.row1 != 0x0F
+    }
+
+    /// The row holding `key` and its bit in that row.
+    fn key_line(&mut self, key: KeypadKey) -> (&mut u8, u8) {
         match key {
-            KeypadKey::Right => self.row0 &= !(1 << 0),
-            KeypadKey::Left => self.row0 &= !(1 << 1),
-            KeypadKey::Up => self.row0 &= !(1 << 2),
-            KeypadKey::Down => self.row0 &= !(1 << 3),
-            KeypadKey::A => self.row1 &= !(1 << 0),
-            KeypadKey::B => self.row1 &= !(1 << 1),
-            KeypadKey::Select => self.row1 &= !(1 << 2),
-            KeypadKey::Start => self.row1 &= !(1 << 3),
+            KeypadKey::Right => (&mut self.row0, 1 << 0),
+            KeypadKey::Left => (&mut self.row0, 1 << 1),
+            KeypadKey::Up => (&mut self.row0, 1 << 2),
+            KeypadKey::Down => (&mut self.row0, 1 << 3),
+            KeypadKey::A => (&mut self.row1, 1 << 0),
+            KeypadKey::B => (&mut self.row1, 1 << 1),
+            KeypadKey::Select => (&mut self.row1, 1 << 2),
+            KeypadKey::Start => (&mut self.row1, 1 << 3),
         }
+    }
+
+    pub fn keydown(&mut self, key: KeypadKey) {
+        let (row, bit) = self.key_line(key);
+        *row &= !bit;
         self.update();
     }
 
     pub fn keyup(&mut self, key: KeypadKey) {
-        match key {
-            KeypadKey::Right => self.row0 |= 1 << 0,
-            KeypadKey::Left => self.row0 |= 1 << 1,
-            KeypadKey::Up => self.row0 |= 1 << 2,
-            KeypadKey::Down => self.row0 |= 1 << 3,
-            KeypadKey::A => self.row1 |= 1 << 0,
-            KeypadKey::B => self.row1 |= 1 << 1,
-            KeypadKey::Select => self.row1 |= 1 << 2,
-            KeypadKey::Start => self.row1 |= 1 << 3,
+        let (row, bit) = self.key_line(key);
+        *row |= bit;
+        self.update();
+    }
+
+    /// Hold exactly the keys in `pressed` and release all others, in a single update, so no
+    /// in-between state can raise the joypad interrupt.
+    pub fn set_keys(&mut self, pressed: &[KeypadKey]) {
+        self.row0 = 0x0F;
+        self.row1 = 0x0F;
+        for &key in pressed {
+            let (row, bit) = self.key_line(key);
+            *row &= !bit;
         }
         self.update();
     }
@@ -173,4 +200,51 @@ mod test {
             keypad.keyup(keys1[i]);
         }
     }
+
+    #[test]
+    fn set_keys() {
+        let mut keypad = super::Keypad::new();
+        keypad.keydown(KeypadKey::B);
+        keypad.set_keys(&[KeypadKey::Up, KeypadKey::A]);
+
+        keypad.wb(0x20);
+        assert_eq!(keypad.rb(), 0xEF & !(1 << 2));
+        keypad.wb(0x10);
+        assert_eq!(keypad.rb(), 0xDF & !(1 << 0));
+
+        keypad.set_keys(&[]);
+        keypad.wb(0x00);
+        assert_eq!(keypad.rb(), 0xCF);
+    }
+
+    #[test]
+    fn interrupt_on_any_line() {
+        let mut keypad = super::Keypad::new();
+        keypad.wb(0x20);
+        keypad.keydown(KeypadKey::A);
+        assert_eq!(keypad.interrupt, 0x10);
+        keypad.interrupt = 0;
+
+        keypad.wb(0x10);
+        assert_eq!(keypad.rb(), 0xDF & !(1 << 0));
+        assert_eq!(keypad.interrupt, 0);
+
+        keypad.keydown(KeypadKey::Right);
+        assert_eq!(keypad.interrupt, 0x10);
+        keypad.interrupt = 0;
+
+        keypad.keyup(KeypadKey::A);
+        keypad.set_keys(&[KeypadKey::Right]);
+        assert_eq!(keypad.interrupt, 0);
+    }
+
+    #[test]
+    fn unused_bits_read_high() {
+        let mut keypad = super::Keypad::new();
+        keypad.set_mask(0xFF);
+        for &v in &[0x00, 0x10, 0x20, 0x30, 0x0F, 0xC0, 0xFF] {
+            keypad.wb(v);
+            assert_eq!(keypad.rb() & 0xC0, 0xC0);
+        }
+    }
 }
@@ -0,0 +1,383 @@
+use crate::mmu::{MIRROR_PARTY_COUNT, MIRROR_PARTY_START};
+
+/// Where Pokemon Gold/Silver (Gen 2) keep their game state. All addresses are CPU addresses
+/// in WRAM; the decoding functions read them through `rb`, so they see the WRAM bank that is
+/// currently mapped.
+pub struct PokemonGenTwoLayout;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct PlayerPosition {
+    pub map_bank: u8,
+    pub map_id: u8,
+    pub x: u8,
+    pub y: u8,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct BattleState {
+    /// Battle type, nonzero while in battle
+    pub in_battle: u8,
+    pub enemy_species: u8,
+    pub enemy_level: u8,
+    pub enemy_hp: u16,
+    pub enemy_max_hp: u16,
+    /// HP of the player's Pokemon that is currently fighting
+    pub player_hp: u16,
+}
+
+/// What the game is showing, as far as the layout can tell.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum GameScreen {
+    /// No map is loaded, e.g. the title screen or intro.
+    NoMap,
+    Battle,
+    /// A menu is open on top of the map.
+    Menu,
+    /// On the map with nothing open, the player can walk.
+    Overworld,
+}
+
+/// One party slot as stored in the mirror.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct PartyMember {
+    pub species: u8,
+    pub level: u8,
+    pub hp: u16,
+    pub max_hp: u16,
+    pub status: u8,
+    pub moves: [u8; 4],
+}
+
+/// Non-volatile status condition of a Pokemon, decoded from the Gen 2 status byte:
+///
+/// | bits | meaning                              |
+/// |------|--------------------------------------|
+/// | 0-2  | sleep counter, turns left (0: awake) |
+/// | 3    | poison                               |
+/// | 4    | burn                                 |
+/// | 5    | freeze                               |
+/// | 6    | paralysis                            |
+/// | 7    | unused                               |
+///
+/// The game sets at most one of them. Badly poisoned (Toxic) is a battle-only condition and
+/// shows as plain poison here.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct StatusCondition {
+    pub sleep_turns: u8,
+    pub poison: bool,
+    pub burn: bool,
+    pub freeze: bool,
+    pub paralysis: bool,
+}
+
+impl StatusCondition {
+    pub fn from_byte(status: u8) -> StatusCondition {
+        StatusCondition {
+            sleep_turns: status & 0x07,
+            poison: status & 0x08 != 0,
+            burn: status & 0x10 != 0,
+            freeze: status & 0x20 != 0,
+            paralysis: status & 0x40 != 0,
+        }
+    }
+
+    pub fn asleep(&self) -> bool {
+        self.sleep_turns != 0
+    }
+
+    /// Whether no condition is set.
+    pub fn healthy(&self) -> bool {
+        *self == StatusCondition::default()
+    }
+}
+
+fn read_u16_be(rb: &mut impl FnMut(u16) -> u8, a: u16) -> u16 {
+    u16::from_be_bytes([rb(a), rb(a + 1)])
+}
+
+impl PokemonGenTwoLayout {
+    pub const MAP_BANK: u16 = 0xDA00;
+    pub const MAP_ID: u16 = 0xDA01;
+    pub const PLAYER_X: u16 = 0xD20D;
+    pub const PLAYER_Y: u16 = 0xD20E;
+    pub const MAP_WIDTH: u16 = 0xD19F;
+    /// Block ids of the loaded map, surrounded by a border of `MAP_BORDER` blocks on each side.
+    pub const OVERWORLD_MAP: u16 = 0xC800;
+    pub const OVERWORLD_MAP_SIZE: u16 = 1300;
+    pub const MAP_BORDER: u16 = 3;
+    pub const IN_BATTLE: u16 = 0xD116;
+    pub const ENEMY_SPECIES: u16 = 0xD0ED;
+    pub const ENEMY_LEVEL: u16 = 0xD0FC;
+    /// Big endian, followed by the max HP
+    pub const ENEMY_HP: u16 = 0xD0FF;
+    pub const ENEMY_MAX_HP: u16 = 0xD101;
+    /// Big endian HP of the active battle Pokemon of the player
+    pub const BATTLE_MON_HP: u16 = 0xCB1C;
+    /// 1-based row of the cursor in the active menu (wMenuCursorY), 0 without a menu.
+    pub const MENU_CURSOR_Y: u16 = 0xCFA9;
+    pub const PARTY_COUNT: u16 = 0xDA22;
+    /// The party_struct of each party member, `PARTY_MON_SIZE` bytes apart.
+    pub const PARTY_MONS: u16 = 0xDA2A;
+    pub const PARTY_MON_SIZE: u16 = 48;
+    /// Offsets into a party_struct. HP and max HP are big endian.
+    pub const PARTY_MON_SPECIES: u16 = 0x00;
+    pub const PARTY_MON_MOVES: u16 = 0x02;
+    pub const PARTY_MON_LEVEL: u16 = 0x1F;
+    pub const PARTY_MON_STATUS: u16 = 0x20;
+    pub const PARTY_MON_HP: u16 = 0x22;
+    pub const PARTY_MON_MAX_HP: u16 = 0x24;
+    /// Size of a party slot in the mirror: species, level, HP and max HP (little endian),
+    /// status and four moves.
+    pub const MIRROR_PARTY_SLOT_SIZE: usize = 11;
+    pub const PARTY_MAX: usize = 6;
+
+    /// All party members in the mirror, lead first.
+    pub fn party(mirror: &[u8]) -> Vec<PartyMember> {
+        let count = (mirror[MIRROR_PARTY_COUNT] as usize).min(Self::PARTY_MAX);
+        (0..count)
+            .filter_map(|slot| Self::party_member(mirror, slot))
+            .collect()
+    }
+
+    /// The party member in `slot` of the mirror, or None if the party is smaller.
+    pub fn party_member(mirror: &[u8], slot: usize) -> Option<PartyMember> {
+        let count = (mirror[MIRROR_PARTY_COUNT] as usize).min(Self::PARTY_MAX);
+        if slot >= count {
+            return None;
+        }
+        let start = MIRROR_PARTY_START + slot * Self::MIRROR_PARTY_SLOT_SIZE;
+        let b = &mirror[start..start + Self::MIRROR_PARTY_SLOT_SIZE];
+        Some(PartyMember {
+            species: b[0],
+            level: b[1],
+            hp: u16::from_le_bytes([b[2], b[3]]),
+            max_hp: u16::from_le_bytes([b[4], b[5]]),
+            status: b[6],
+            moves: [b[7], b[8], b[9], b[10]],
+        })
+    }
+
+    /// The raw status byte of the party member in `slot`, see `StatusCondition` for its bits.
+    pub fn party_status(mirror: &[u8], slot: usize) -> Option<u8> {
+        Self::party_member(mirror, slot).map(|member| member.status)
+    }
+
+    /// Current map and player coordinates, or None while no map is loaded (map bank and map
+    /// id both zero, e.g. on the title screen).
+    pub fn player_position(rb: &mut impl FnMut(u16) -> u8) -> Option<PlayerPosition> {
+        let map_bank = rb(Self::MAP_BANK);
+        let map_id = rb(Self::MAP_ID);
+        if map_bank == 0 && map_id == 0 {
+            return None;
+        }
+        Some(PlayerPosition {
+            map_bank,
+            map_id,
+            x: rb(Self::PLAYER_X),
+            y: rb(Self::PLAYER_Y),
+        })
+    }
+
+    /// The current battle, or None outside of battle.
+    pub fn battle_state(rb: &mut impl FnMut(u16) -> u8) -> Option<BattleState> {
+        let in_battle = rb(Self::IN_BATTLE);
+        if in_battle == 0 {
+            return None;
+        }
+        Some(BattleState {
+            in_battle,
+            enemy_species: rb(Self::ENEMY_SPECIES),
+            enemy_level: rb(Self::ENEMY_LEVEL),
+            enemy_hp: read_u16_be(rb, Self::ENEMY_HP),
+            enemy_max_hp: read_u16_be(rb, Self::ENEMY_MAX_HP),
+            player_hp: read_u16_be(rb, Self::BATTLE_MON_HP),
+        })
+    }
+
+    /// 0-based index of the selected item in the active menu, or None if no menu is open.
+    pub fn menu_cursor(rb: &mut impl FnMut(u16) -> u8) -> Option<u8> {
+        rb(Self::MENU_CURSOR_Y).checked_sub(1)
+    }
+
+    /// Which screen the game is on, from the map, battle and menu state. Text boxes that are
+    /// not menus are not detected and count as overworld.
+    pub fn game_screen(rb: &mut impl FnMut(u16) -> u8) -> GameScreen {
+        if Self::player_position(rb).is_none() {
+            GameScreen::NoMap
+        } else if rb(Self::IN_BATTLE) != 0 {
+            GameScreen::Battle
+        } else if Self::menu_cursor(rb).is_some() {
+            GameScreen::Menu
+        } else {
+            GameScreen::Overworld
+        }
+    }
+
+    /// The (2 * radius + 1) x (2 * radius + 1) map blocks centered on the player, row by row,
+    /// read from the overworld map buffer. A block covers 2x2 player steps. Positions outside
+    /// the buffer read as 0.
+    pub fn local_map(rb: &mut impl FnMut(u16) -> u8, radius: u8) -> Vec<u8> {
+        let stride = rb(Self::MAP_WIDTH) as i32 + 2 * Self::MAP_BORDER as i32;
+        let center_x = (rb(Self::PLAYER_X) / 2) as i32 + Self::MAP_BORDER as i32;
+        let center_y = (rb(Self::PLAYER_Y) / 2) as i32 + Self::MAP_BORDER as i32;
+        let radius = radius as i32;
+
+        let mut grid = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+        for y in center_y - radius..=center_y + radius {
+            for x in center_x - radius..=center_x + radius {
+                let index = y * stride + x;
+                let inside =
+                    x >= 0 && x < stride && y >= 0 && index < Self::OVERWORLD_MAP_SIZE as i32;
+                grid.push(if inside {
+                    rb(Self::OVERWORLD_MAP + index as u16)
+                } else {
+                    0
+                });
+            }
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BattleState, GameScreen, PartyMember, PlayerPosition, PokemonGenTwoLayout, StatusCondition,
+    };
+    use crate::mmu::{MIRROR_PARTY_COUNT, MIRROR_PARTY_START, MIRROR_SIZE};
+
+    fn reader(wram: &[(u16, u8)]) -> impl FnMut(u16) -> u8 + '_ {
+        move |a| {
+            wram.iter()
+                .find(|(addr, _)| *addr == a)
+                .map_or(0, |(_, v)| *v)
+        }
+    }
+
+    #[test]
+    fn player_position() {
+        let wram = [(0xDA00, 3), (0xDA01, 5), (0xD20D, 7), (0xD20E, 9)];
+        assert_eq!(
+            PokemonGenTwoLayout::player_position(&mut reader(&wram)),
+            Some(PlayerPosition {
+                map_bank: 3,
+                map_id: 5,
+                x: 7,
+                y: 9
+            })
+        );
+        assert_eq!(PokemonGenTwoLayout::player_position(&mut reader(&[])), None);
+    }
+
+    #[test]
+    fn battle_state() {
+        let wram = [
+            (0xD116, 1),
+            (0xD0ED, 19),
+            (0xD0FC, 4),
+            (0xD0FF, 0x00),
+            (0xD100, 12),
+            (0xD101, 0x01),
+            (0xD102, 0x2C),
+            (0xCB1C, 0x00),
+            (0xCB1D, 20),
+        ];
+        assert_eq!(
+            PokemonGenTwoLayout::battle_state(&mut reader(&wram)),
+            Some(BattleState {
+                in_battle: 1,
+                enemy_species: 19,
+                enemy_level: 4,
+                enemy_hp: 12,
+                enemy_max_hp: 300,
+                player_hp: 20,
+            })
+        );
+        assert_eq!(
+            PokemonGenTwoLayout::battle_state(&mut reader(&wram[1..])),
+            None
+        );
+    }
+
+    #[test]
+    fn party_member() {
+        let mut mirror = vec![0; MIRROR_SIZE];
+        mirror[MIRROR_PARTY_COUNT] = 2;
+        mirror[MIRROR_PARTY_START..MIRROR_PARTY_START + 22].copy_from_slice(&[
+            155, 5, 20, 0, 0x2C, 0x01, 0, 33, 43, 0, 0, // Cyndaquil
+            16, 3, 1, 0, 12, 0, 0x08, 33, 0, 0, 0, // poisoned Pidgey
+        ]);
+        assert_eq!(
+            PokemonGenTwoLayout::party_member(&mirror, 0),
+            Some(PartyMember {
+                species: 155,
+                level: 5,
+                hp: 20,
+                max_hp: 300,
+                status: 0,
+                moves: [33, 43, 0, 0],
+            })
+        );
+        let second = PokemonGenTwoLayout::party_member(&mirror, 1).unwrap();
+        assert_eq!((second.species, second.level, second.hp), (16, 3, 1));
+        assert_eq!(second.status, 0x08);
+        assert_eq!(PokemonGenTwoLayout::party_member(&mirror, 2), None);
+        assert_eq!(PokemonGenTwoLayout::party(&mirror).len(), 2);
+    }
+
+    #[test]
+    fn status_condition() {
+        assert!(StatusCondition::from_byte(0).healthy());
+        let asleep = StatusCondition::from_byte(0x03);
+        assert_eq!(asleep.sleep_turns, 3);
+        assert!(asleep.asleep() && !asleep.poison);
+        assert!(StatusCondition::from_byte(0x08).poison);
+        assert!(StatusCondition::from_byte(0x10).burn);
+        assert!(StatusCondition::from_byte(0x20).freeze);
+        let paralyzed = StatusCondition::from_byte(0x40);
+        assert!(paralyzed.paralysis && !paralyzed.asleep() && !paralyzed.healthy());
+
+        let mut mirror = vec![0; MIRROR_SIZE];
+        mirror[MIRROR_PARTY_COUNT] = 1;
+        mirror[MIRROR_PARTY_START + 6] = 0x10;
+        assert_eq!(PokemonGenTwoLayout::party_status(&mirror, 0), Some(0x10));
+        assert_eq!(PokemonGenTwoLayout::party_status(&mirror, 1), None);
+    }
+
+    #[test]
+    fn menu_cursor() {
+        let wram = [(PokemonGenTwoLayout::MENU_CURSOR_Y, 3)];
+        assert_eq!(
+            PokemonGenTwoLayout::menu_cursor(&mut reader(&wram)),
+            Some(2)
+        );
+        assert_eq!(PokemonGenTwoLayout::menu_cursor(&mut reader(&[])), None);
+    }
+
+    #[test]
+    fn game_screen() {
+        let map = [(0xDA00, 1), (0xDA01, 2)];
+        let screen = |wram: &[(u16, u8)]| PokemonGenTwoLayout::game_screen(&mut reader(wram));
+        assert_eq!(screen(&[]), GameScreen::NoMap);
+        assert_eq!(screen(&map), GameScreen::Overworld);
+        assert_eq!(screen(&[map[0], map[1], (0xD116, 1)]), GameScreen::Battle);
+        assert_eq!(screen(&[map[0], map[1], (0xCFA9, 1)]), GameScreen::Menu);
+    }
+
+    #[test]
+    fn local_map() {
+        // A 4 block wide map, so rows of 10 blocks; every block holds its buffer index
+        let mut rb = |a: u16| match a {
+            PokemonGenTwoLayout::MAP_WIDTH => 4,
+            PokemonGenTwoLayout::PLAYER_X | PokemonGenTwoLayout::PLAYER_Y => 2,
+            0xC800..=0xCD13 => (a - 0xC800) as u8,
+            _ => 0xFF,
+        };
+        assert_eq!(
+            PokemonGenTwoLayout::local_map(&mut rb, 1),
+            vec![33, 34, 35, 43, 44, 45, 53, 54, 55]
+        );
+        assert_eq!(PokemonGenTwoLayout::local_map(&mut rb, 0), vec![44]);
+        assert_eq!(PokemonGenTwoLayout::local_map(&mut rb, 5)[0], 0);
+    }
+}
@@ -1,23 +1,46 @@
 #![crate_name = "rgirl"]
 #![crate_type = "lib"]
 
-pub use crate::gpu::{SCREEN_H, SCREEN_W};
+pub use crate::cheat::Cheat;
+pub use crate::gbmode::GbMode;
+pub use crate::gpu::{FrameFormat, DEFAULT_DMG_PALETTE, SCREEN_H, SCREEN_W};
 pub use crate::keypad::KeypadKey;
-pub use crate::serial::SerialCallback;
-pub use crate::sound::AudioPlayer;
+pub use crate::layout::{
+    BattleState, GameScreen, PartyMember, PlayerPosition, PokemonGenTwoLayout, StatusCondition,
+};
+pub use crate::mbc::RumbleCallback;
+pub use crate::mirror::{FieldEncoding, MirrorField, MirrorSpec};
+pub use crate::mmu::{DEFAULT_WRAM_SEED, MIRROR_SIZE, MIRROR_VISIBLE_SIZE};
+pub use crate::register::CpuRegs;
+pub use crate::reward::RewardConfig;
+pub use crate::serial::{BufferedSerial, SerialCallback, TeeSerial};
+pub use crate::sound::{AudioPlayer, NullAudioPlayer, SoundConfig};
 
 pub mod device;
+pub mod error;
 
+mod cheat;
 mod cpu;
+mod delta;
 mod gbmode;
 mod gpu;
 mod keypad;
+mod layout;
 mod mbc;
+mod mirror;
 mod mmu;
 mod printer;
 mod register;
+mod reward;
 mod serial;
 mod sound;
+#[cfg(test)]
+mod testrom;
 mod timer;
 
 pub type StrResult<T> = Result<T, &'static str>;
+
+/// Cycles per second of the CPU clock in single speed mode.
+pub const CLOCK_HZ: u32 = 4194304;
+/// Cycles per second of the CPU clock in CGB double speed mode.
+pub const CLOCK_HZ_DOUBLE_SPEED: u32 = 2 * CLOCK_HZ;
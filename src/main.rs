@@ -607,22 +607,6 @@ impl rgirl::AudioPlayer for CpalPlayer {
     }
 }
 
-struct NullAudioPlayer {}
-
-impl rgirl::AudioPlayer for NullAudioPlayer {
-    fn play(&mut self, _buf_left: &[f32], _buf_right: &[f32]) {
-        // Do nothing
-    }
-
-    fn samples_rate(&self) -> u32 {
-        44100
-    }
-
-    fn underflowed(&self) -> bool {
-        false
-    }
-}
-
 fn run_test_mode(filename: &str, classic_mode: bool, skip_checksum: bool) -> i32 {
     let opt_cpu = match classic_mode {
         true => Device::new(filename, skip_checksum, None),
@@ -637,7 +621,7 @@ fn run_test_mode(filename: &str, classic_mode: bool, skip_checksum: bool) -> i32
     };
 
     cpu.set_stdout(true);
-    cpu.enable_audio(Box::new(NullAudioPlayer {}), false);
+    cpu.enable_audio(Box::new(rgirl::NullAudioPlayer), false);
 
     // from masonforest, https://stackoverflow.com/a/55201400 (CC BY-SA 4.0)
     let stdin_channel = spawn_stdin_channel();
@@ -37,6 +37,9 @@ impl MBC for MBC0 {
     fn dumpram(&self) -> Vec<u8> {
         Vec::new()
     }
+    fn ram_size(&self) -> usize {
+        0
+    }
     fn check_and_reset_ram_updated(&mut self) -> bool {
         false
     }
@@ -1,4 +1,5 @@
-use crate::mbc::{ram_banks, rom_banks, MBC};
+use crate::error;
+use crate::mbc::{ram_banks, rom_banks, SaveTracker, MBC};
 use crate::StrResult;
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +9,8 @@ pub struct MBC1 {
     ram: Vec<u8>,
     ram_on: bool,
     ram_updated: bool,
+    #[serde(default)]
+    save_tracker: SaveTracker,
     banking_mode: u8,
     rombank: usize,
     rambank: usize,
@@ -34,6 +37,7 @@ impl MBC1 {
             rombank: 1,
             rambank: 0,
             ram_updated: false,
+            save_tracker: SaveTracker::default(),
             has_battery: has_battery,
             rombanks: rombanks,
             rambanks: rambanks,
@@ -74,6 +78,7 @@ impl MBC for MBC1 {
         match a {
             0x0000..=0x1FFF => {
                 self.ram_on = v & 0xF == 0xA;
+                self.save_tracker.ram_enabled(self.ram_on);
             }
             0x2000..=0x3FFF => {
                 let lower_bits = match (v as usize) & 0x1F {
@@ -111,16 +116,23 @@ impl MBC for MBC1 {
         if address < self.ram.len() {
             self.ram[address] = v;
             self.ram_updated = true;
+            self.save_tracker.ram_written();
         }
     }
 
+    fn poke_ram(&mut self, a: u16, v: u8) {
+        let ram_on = std::mem::replace(&mut self.ram_on, true);
+        self.writeram(a, v);
+        self.ram_on = ram_on;
+    }
+
     fn is_battery_backed(&self) -> bool {
         self.has_battery
     }
 
     fn loadram(&mut self, ramdata: &[u8]) -> StrResult<()> {
         if ramdata.len() != self.ram.len() {
-            return Err("Loaded RAM has incorrect length");
+            return Err(error::RAM_WRONG_LENGTH);
         }
 
         self.ram = ramdata.to_vec();
@@ -132,6 +144,14 @@ impl MBC for MBC1 {
         self.ram.to_vec()
     }
 
+    fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    fn just_saved(&mut self) -> bool {
+        self.save_tracker.just_saved()
+    }
+
     fn check_and_reset_ram_updated(&mut self) -> bool {
         let result = self.ram_updated;
         self.ram_updated = false;
@@ -1,4 +1,5 @@
-use crate::mbc::{rom_banks, MBC};
+use crate::error;
+use crate::mbc::{rom_banks, SaveTracker, MBC};
 use crate::StrResult;
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +9,8 @@ pub struct MBC2 {
     ram: Vec<u8>,
     ram_on: bool,
     ram_updated: bool,
+    #[serde(default)]
+    save_tracker: SaveTracker,
     rombank: usize,
     has_battery: bool,
     rombanks: usize,
@@ -26,6 +29,7 @@ impl MBC2 {
             ram: vec![0; 512],
             ram_on: false,
             ram_updated: false,
+            save_tracker: SaveTracker::default(),
             rombank: 1,
             has_battery: has_battery,
             rombanks: rombanks,
@@ -54,6 +58,7 @@ impl MBC for MBC2 {
             0x0000..=0x3FFF => {
                 if a & 0x100 == 0 {
                     self.ram_on = v & 0xF == 0xA;
+                    self.save_tracker.ram_enabled(self.ram_on);
                 } else {
                     self.rombank = match (v as usize) & 0x0F {
                         0 => 1,
@@ -71,6 +76,13 @@ impl MBC for MBC2 {
         }
         self.ram[(a as usize) & 0x1FF] = v | 0xF0;
         self.ram_updated = true;
+        self.save_tracker.ram_written();
+    }
+
+    fn poke_ram(&mut self, a: u16, v: u8) {
+        let ram_on = std::mem::replace(&mut self.ram_on, true);
+        self.writeram(a, v);
+        self.ram_on = ram_on;
     }
 
     fn is_battery_backed(&self) -> bool {
@@ -79,7 +91,7 @@ impl MBC for MBC2 {
 
     fn loadram(&mut self, ramdata: &[u8]) -> StrResult<()> {
         if ramdata.len() != self.ram.len() {
-            return Err("Loaded RAM has incorrect length");
+            return Err(error::RAM_WRONG_LENGTH);
         }
 
         self.ram = ramdata.to_vec();
@@ -91,6 +103,14 @@ impl MBC for MBC2 {
         self.ram.to_vec()
     }
 
+    fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    fn just_saved(&mut self) -> bool {
+        self.save_tracker.just_saved()
+    }
+
     fn check_and_reset_ram_updated(&mut self) -> bool {
         let result = self.ram_updated;
         self.ram_updated = false;
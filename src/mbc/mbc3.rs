@@ -1,4 +1,5 @@
-use crate::mbc::{ram_banks, MBC};
+use crate::error;
+use crate::mbc::{ram_banks, SaveTracker, MBC};
 use crate::StrResult;
 
 use serde::{Deserialize, Serialize};
@@ -16,6 +17,8 @@ pub struct MBC3 {
     selectrtc: bool,
     ram_on: bool,
     ram_updated: bool,
+    #[serde(default)]
+    save_tracker: SaveTracker,
     has_battery: bool,
     rtc_ram: [u8; 5],
     rtc_ram_latch: [u8; 5],
@@ -48,6 +51,7 @@ impl MBC3 {
             selectrtc: false,
             ram_on: false,
             ram_updated: false,
+            save_tracker: SaveTracker::default(),
             has_battery: has_battery,
             rtc_ram: [0u8; 5],
             rtc_ram_latch: [0u8; 5],
@@ -139,7 +143,10 @@ impl MBC for MBC3 {
     }
     fn writerom(&mut self, a: u16, v: u8) {
         match a {
-            0x0000..=0x1FFF => self.ram_on = (v & 0x0F) == 0x0A,
+            0x0000..=0x1FFF => {
+                self.ram_on = (v & 0x0F) == 0x0A;
+                self.save_tracker.ram_enabled(self.ram_on);
+            }
             0x2000..=0x3FFF => {
                 self.rombank = match v & 0x7F {
                     0 => 1,
@@ -161,6 +168,7 @@ impl MBC for MBC3 {
         if !self.selectrtc && self.rambank < self.rambanks {
             self.ram[self.rambank * 0x2000 | ((a as usize) & 0x1FFF)] = v;
             self.ram_updated = true;
+            self.save_tracker.ram_written();
         } else if self.selectrtc && self.rambank < 5 {
             self.calc_rtc_reg();
             let vmask = match self.rambank {
@@ -172,7 +180,57 @@ impl MBC for MBC3 {
             self.rtc_ram[self.rambank] = v & vmask;
             self.calc_rtc_zero();
             self.ram_updated = true;
+            self.save_tracker.ram_written();
+        }
+    }
+
+    fn poke_ram(&mut self, a: u16, v: u8) {
+        let ram_on = std::mem::replace(&mut self.ram_on, true);
+        self.writeram(a, v);
+        self.ram_on = ram_on;
+    }
+
+    fn rtc_seconds(&self) -> Option<u64> {
+        let zero = self.rtc_zero?;
+        if self.rtc_ram[4] & 0x40 == 0x40 {
+            // Halted, the registers hold the time
+            let days = ((self.rtc_ram[4] as u64 & 0x1) << 8) | (self.rtc_ram[3] as u64);
+            return Some(
+                self.rtc_ram[0] as u64
+                    + self.rtc_ram[1] as u64 * 60
+                    + self.rtc_ram[2] as u64 * 3600
+                    + days * 3600 * 24,
+            );
         }
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        // The day counter has 9 bits
+        Some(now.saturating_sub(zero) % (512 * 3600 * 24))
+    }
+
+    /// The clock's time base (u64 big endian, as in `dumpram`), then its 5 registers and
+    /// their latched copy.
+    fn dump_rtc(&self) -> Option<Vec<u8>> {
+        let zero = self.rtc_zero?;
+        let mut data = zero.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.rtc_ram);
+        data.extend_from_slice(&self.rtc_ram_latch);
+        Some(data)
+    }
+
+    fn load_rtc(&mut self, data: &[u8]) -> StrResult<()> {
+        if self.rtc_zero.is_none() {
+            return Err(error::NO_CLOCK);
+        }
+        if data.len() != 18 {
+            return Err(error::CLOCK_WRONG_SIZE);
+        }
+        let (zero, regs) = data.split_at(8);
+        self.rtc_zero = Some(u64::from_be_bytes(zero.try_into().unwrap()));
+        self.rtc_ram.copy_from_slice(&regs[..5]);
+        self.rtc_ram_latch.copy_from_slice(&regs[5..]);
+        Ok(())
     }
 
     fn is_battery_backed(&self) -> bool {
@@ -181,7 +239,7 @@ impl MBC for MBC3 {
 
     fn loadram(&mut self, ramdata: &[u8]) -> StrResult<()> {
         if ramdata.len() != 8 + self.ram.len() {
-            return Err("Loaded ram is too small");
+            return Err(error::RAM_TOO_SMALL);
         }
 
         let (int_bytes, rest) = ramdata.split_at(8);
@@ -213,6 +271,14 @@ impl MBC for MBC3 {
         file
     }
 
+    fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    fn just_saved(&mut self) -> bool {
+        self.save_tracker.just_saved()
+    }
+
     fn check_and_reset_ram_updated(&mut self) -> bool {
         let result = self.ram_updated;
         self.ram_updated = false;
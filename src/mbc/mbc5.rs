@@ -1,4 +1,5 @@
-use crate::mbc::{ram_banks, rom_banks, MBC};
+use crate::error;
+use crate::mbc::{ram_banks, rom_banks, SaveTracker, MBC};
 use crate::StrResult;
 use serde::{Deserialize, Serialize};
 
@@ -10,9 +11,15 @@ pub struct MBC5 {
     rambank: usize,
     ram_on: bool,
     ram_updated: bool,
+    #[serde(default)]
+    save_tracker: SaveTracker,
     has_battery: bool,
     rombanks: usize,
     rambanks: usize,
+    #[serde(default)]
+    has_rumble: bool,
+    #[serde(default)]
+    rumble: bool,
 }
 
 impl MBC5 {
@@ -28,6 +35,7 @@ impl MBC5 {
         };
         let ramsize = 0x2000 * rambanks;
         let rombanks = rom_banks(data[0x148]);
+        let has_rumble = matches!(subtype, 0x1C..=0x1E);
 
         let res = MBC5 {
             rom: data,
@@ -35,10 +43,13 @@ impl MBC5 {
             rombank: 1,
             rambank: 0,
             ram_updated: false,
+            save_tracker: SaveTracker::default(),
             ram_on: false,
             has_battery: has_battery,
             rombanks: rombanks,
             rambanks: rambanks,
+            has_rumble,
+            rumble: false,
         };
 
         Ok(res)
@@ -63,7 +74,10 @@ impl MBC for MBC5 {
     }
     fn writerom(&mut self, a: u16, v: u8) {
         match a {
-            0x0000..=0x1FFF => self.ram_on = v & 0x0F == 0x0A,
+            0x0000..=0x1FFF => {
+                self.ram_on = v & 0x0F == 0x0A;
+                self.save_tracker.ram_enabled(self.ram_on);
+            }
             0x2000..=0x2FFF => {
                 self.rombank = ((self.rombank & 0x100) | (v as usize)) % self.rombanks
             }
@@ -71,7 +85,16 @@ impl MBC for MBC5 {
                 self.rombank =
                     ((self.rombank & 0x0FF) | (((v & 0x1) as usize) << 8)) % self.rombanks
             }
-            0x4000..=0x5FFF => self.rambank = ((v & 0x0F) as usize) % self.rambanks,
+            0x4000..=0x5FFF => {
+                // Rumble carts drive the motor with bit 3 instead of using it for the bank
+                let bank = if self.has_rumble {
+                    self.rumble = v & 0x08 != 0;
+                    v & 0x07
+                } else {
+                    v & 0x0F
+                };
+                self.rambank = (bank as usize) % self.rambanks.max(1);
+            }
             0x6000..=0x7FFF => { /* ? */ }
             _ => panic!("Could not write to {:04X} (MBC5)", a),
         }
@@ -82,6 +105,17 @@ impl MBC for MBC5 {
         }
         self.ram[self.rambank * 0x2000 | ((a as usize) & 0x1FFF)] = v;
         self.ram_updated = true;
+        self.save_tracker.ram_written();
+    }
+
+    fn poke_ram(&mut self, a: u16, v: u8) {
+        let ram_on = std::mem::replace(&mut self.ram_on, true);
+        self.writeram(a, v);
+        self.ram_on = ram_on;
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
     }
 
     fn is_battery_backed(&self) -> bool {
@@ -90,7 +124,7 @@ impl MBC for MBC5 {
 
     fn loadram(&mut self, ramdata: &[u8]) -> StrResult<()> {
         if ramdata.len() != self.ram.len() {
-            return Err("Loaded RAM has incorrect length");
+            return Err(error::RAM_WRONG_LENGTH);
         }
 
         self.ram = ramdata.to_vec();
@@ -102,6 +136,14 @@ impl MBC for MBC5 {
         self.ram.to_vec()
     }
 
+    fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    fn just_saved(&mut self) -> bool {
+        self.save_tracker.just_saved()
+    }
+
     fn check_and_reset_ram_updated(&mut self) -> bool {
         let result = self.ram_updated;
         self.ram_updated = false;
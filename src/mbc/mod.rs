@@ -1,3 +1,4 @@
+use crate::error;
 use crate::StrResult;
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
@@ -11,17 +12,54 @@ mod mbc2;
 mod mbc3;
 mod mbc5;
 
+/// Notified when a cartridge switches its rumble motor on or off.
+pub trait RumbleCallback: Send {
+    fn call(&mut self, on: bool);
+}
+
 #[typetag::serde(tag = "type")]
 pub trait MBC: Send {
     fn readrom(&self, a: u16) -> u8;
     fn readram(&self, a: u16) -> u8;
     fn writerom(&mut self, a: u16, v: u8);
     fn writeram(&mut self, a: u16, v: u8);
+    /// Write cartridge RAM like `writeram`, but even while the game has it disabled.
+    fn poke_ram(&mut self, a: u16, v: u8) {
+        self.writeram(a, v)
+    }
     fn check_and_reset_ram_updated(&mut self) -> bool;
+    /// True once after the game disabled cartridge RAM following writes to it, which is how
+    /// games finish saving. A good moment to persist the RAM contents.
+    fn just_saved(&mut self) -> bool {
+        false
+    }
+
+    /// Time shown by the cartridge's real-time clock in seconds, days included, for MBCs
+    /// that have one.
+    fn rtc_seconds(&self) -> Option<u64> {
+        None
+    }
+
+    /// The state of the real-time clock, to be restored with `load_rtc`. `None` for MBCs
+    /// without one.
+    fn dump_rtc(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_rtc(&mut self, _data: &[u8]) -> StrResult<()> {
+        Err(error::NO_CLOCK)
+    }
+
+    /// Whether the rumble motor is on, always false for carts without one.
+    fn rumble(&self) -> bool {
+        false
+    }
 
     fn is_battery_backed(&self) -> bool;
     fn loadram(&mut self, ramdata: &[u8]) -> StrResult<()>;
     fn dumpram(&self) -> Vec<u8>;
+    /// Size in bytes of the cartridge RAM, as declared by the header (0 when absent).
+    fn ram_size(&self) -> usize;
 
     fn romname(&self) -> String {
         const TITLE_START: u16 = 0x134;
@@ -45,12 +83,40 @@ pub trait MBC: Send {
     }
 }
 
-pub fn get_mbc(data: Vec<u8>, skip_checksum: bool) -> StrResult<Box<dyn MBC + 'static>> {
+/// Detects the end of a save: RAM writes followed by disabling the RAM.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SaveTracker {
+    written: bool,
+    saved: bool,
+}
+
+impl SaveTracker {
+    pub fn ram_written(&mut self) {
+        self.written = true;
+    }
+
+    /// Call on every write to the RAM enable register.
+    pub fn ram_enabled(&mut self, enabled: bool) {
+        if !enabled && self.written {
+            self.written = false;
+            self.saved = true;
+        }
+    }
+
+    pub fn just_saved(&mut self) -> bool {
+        std::mem::replace(&mut self.saved, false)
+    }
+}
+
+/// Pick the MBC from the cartridge header. Unless `skip_header_validation` is set the header
+/// checksum has to be valid and the image size has to match the ROM-size byte and the MBC.
+pub fn get_mbc(data: Vec<u8>, skip_header_validation: bool) -> StrResult<Box<dyn MBC + 'static>> {
     if data.len() < 0x150 {
-        return Err("Rom size to small");
+        return Err(error::ROM_TOO_SMALL);
     }
-    if !skip_checksum {
+    if !skip_header_validation {
         check_checksum(&data)?;
+        check_rom_size(&data)?;
     }
     match data[0x147] {
         0x00 => mbc0::MBC0::new(data).map(|v| Box::new(v) as Box<dyn MBC>),
@@ -58,43 +124,58 @@ pub fn get_mbc(data: Vec<u8>, skip_checksum: bool) -> StrResult<Box<dyn MBC + 's
         0x05..=0x06 => mbc2::MBC2::new(data).map(|v| Box::new(v) as Box<dyn MBC>),
         0x0F..=0x13 => mbc3::MBC3::new(data).map(|v| Box::new(v) as Box<dyn MBC>),
         0x19..=0x1E => mbc5::MBC5::new(data).map(|v| Box::new(v) as Box<dyn MBC>),
-        _ => Err("Unsupported MBC type"),
+        _ => Err(error::UNSUPPORTED_MBC),
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct FileBackedMBC {
     rampath: path::PathBuf,
+    /// Where the real-time clock is kept next to the save, for MBCs that have one.
+    #[serde(default)]
+    rtcpath: path::PathBuf,
     mbc: Box<dyn MBC>,
 }
 
 impl FileBackedMBC {
-    pub fn new(rompath: path::PathBuf, skip_checksum: bool) -> StrResult<FileBackedMBC> {
+    pub fn new(rompath: path::PathBuf, skip_header_validation: bool) -> StrResult<FileBackedMBC> {
         let mut data = vec![];
         File::open(&rompath)
             .and_then(|mut f| f.read_to_end(&mut data))
             .map_err(|_| "Could not read ROM")?;
-        let mut mbc = get_mbc(data, skip_checksum)?;
+        let mut mbc = get_mbc(data, skip_header_validation)?;
 
         let rampath = rompath.with_extension("gbsave");
+        let rtcpath = rompath.with_extension("gbrtc");
 
         if mbc.is_battery_backed() {
             match fs::File::open(&rampath) {
                 Ok(mut file) => {
                     let mut ramdata: Vec<u8> = vec![];
                     match file.read_to_end(&mut ramdata) {
-                        Err(..) => return Err("Error while reading existing save file"),
+                        Err(..) => return Err(error::SAVE_READ_FAILED),
                         Ok(..) => {
                             mbc.loadram(&ramdata)?;
                         }
                     }
                 }
                 Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
-                Err(_) => return Err("Error loading existing save file"),
+                Err(_) => return Err(error::SAVE_LOAD_FAILED),
+            }
+            if mbc.dump_rtc().is_some() {
+                match fs::read(&rtcpath) {
+                    Ok(rtcdata) => mbc.load_rtc(&rtcdata)?,
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(_) => return Err(error::CLOCK_LOAD_FAILED),
+                }
             }
         }
 
-        Ok(FileBackedMBC { rampath, mbc })
+        Ok(FileBackedMBC {
+            rampath,
+            rtcpath,
+            mbc,
+        })
     }
 }
 
@@ -117,6 +198,10 @@ impl MBC for FileBackedMBC {
         self.mbc.writeram(a, v)
     }
 
+    fn poke_ram(&mut self, a: u16, v: u8) {
+        self.mbc.poke_ram(a, v)
+    }
+
     fn is_battery_backed(&self) -> bool {
         self.mbc.is_battery_backed()
     }
@@ -129,9 +214,33 @@ impl MBC for FileBackedMBC {
         self.mbc.dumpram()
     }
 
+    fn ram_size(&self) -> usize {
+        self.mbc.ram_size()
+    }
+
     fn check_and_reset_ram_updated(&mut self) -> bool {
         self.mbc.check_and_reset_ram_updated()
     }
+
+    fn just_saved(&mut self) -> bool {
+        self.mbc.just_saved()
+    }
+
+    fn rtc_seconds(&self) -> Option<u64> {
+        self.mbc.rtc_seconds()
+    }
+
+    fn dump_rtc(&self) -> Option<Vec<u8>> {
+        self.mbc.dump_rtc()
+    }
+
+    fn load_rtc(&mut self, data: &[u8]) -> StrResult<()> {
+        self.mbc.load_rtc(data)
+    }
+
+    fn rumble(&self) -> bool {
+        self.mbc.rumble()
+    }
 }
 
 impl Drop for FileBackedMBC {
@@ -143,6 +252,9 @@ impl Drop for FileBackedMBC {
                 Err(..) => return,
             };
             let _ = file.write_all(&self.mbc.dumpram());
+            if let Some(rtc) = self.mbc.dump_rtc() {
+                let _ = fs::write(&self.rtcpath, rtc);
+            }
         }
     }
 }
@@ -179,12 +291,139 @@ fn check_checksum(data: &[u8]) -> StrResult<()> {
     }
     match data[0x14D] == value {
         true => Ok(()),
-        false => Err("Cartridge checksum is invalid"),
+        false => Err(error::BAD_CHECKSUM),
+    }
+}
+
+/// Most ROM banks each MBC can address, or None for unknown cartridge types.
+fn max_rom_banks(cart_type: u8) -> Option<usize> {
+    match cart_type {
+        0x00 => Some(2),
+        0x01..=0x03 => Some(128),
+        0x05..=0x06 => Some(16),
+        0x0F..=0x13 => Some(128),
+        0x19..=0x1E => Some(512),
+        _ => None,
+    }
+}
+
+/// The image has to hold exactly the banks the header declares, and no more than the MBC can
+/// address. Otherwise bank switching would wrap around or read past the end.
+fn check_rom_size(data: &[u8]) -> StrResult<()> {
+    let banks = rom_banks(data[0x148]);
+    let too_many = max_rom_banks(data[0x147]).is_some_and(|max| banks > max);
+    if banks == 0 || too_many || data.len() != banks * 0x4000 {
+        return Err(error::ROM_SIZE_MISMATCH);
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
+    use crate::error;
+    use crate::testrom;
+
+    #[test]
+    fn ram_size_from_header() {
+        for &(ram_byte, size) in &[(0x00, 0), (0x02, 0x2000), (0x03, 0x8000)] {
+            let rom = testrom::cartridge(0x03, 0x00, ram_byte, &[]);
+            let mbc = super::get_mbc(rom, false).unwrap();
+            assert_eq!(mbc.ram_size(), size);
+        }
+    }
+
+    #[test]
+    fn just_saved_after_write_and_disable() {
+        let rom = testrom::cartridge(0x03, 0x00, 0x02, &[]);
+        let mut mbc = super::get_mbc(rom, false).unwrap();
+        mbc.writerom(0x0000, 0x0A);
+        mbc.writerom(0x0000, 0x00);
+        assert!(!mbc.just_saved());
+
+        mbc.writerom(0x0000, 0x0A);
+        for a in 0xA000..0xA010 {
+            mbc.writeram(a, 0x42);
+        }
+        assert!(!mbc.just_saved());
+        mbc.writerom(0x0000, 0x00);
+        assert!(mbc.just_saved());
+        assert!(!mbc.just_saved());
+        mbc.writerom(0x0000, 0x00);
+        assert!(!mbc.just_saved());
+    }
+
+    #[test]
+    fn mbc5_rumble_bit() {
+        // MBC5+RUMBLE+RAM: bit 3 of the RAM bank register drives the motor
+        let rom = testrom::cartridge(0x1D, 0x01, 0x03, &[]);
+        let mut mbc = super::get_mbc(rom, false).unwrap();
+        assert!(!mbc.rumble());
+        mbc.writerom(0x4000, 0x09);
+        assert!(mbc.rumble());
+        mbc.writerom(0x0000, 0x0A);
+        mbc.writeram(0xA000, 0x42);
+        mbc.writerom(0x4000, 0x01);
+        assert!(!mbc.rumble());
+        assert_eq!(mbc.readram(0xA000), 0x42);
+
+        // Without a motor, bit 3 selects a RAM bank
+        let rom = testrom::cartridge(0x1A, 0x01, 0x04, &[]);
+        let mut mbc = super::get_mbc(rom, false).unwrap();
+        mbc.writerom(0x4000, 0x08);
+        assert!(!mbc.rumble());
+    }
+
+    #[test]
+    fn rom_size_mismatch() {
+        let mut rom = testrom::cartridge(0x01, 0x02, 0x00, &[]);
+        assert!(super::get_mbc(rom.clone(), false).is_ok());
+        rom.truncate(0x10000);
+        assert_eq!(
+            super::get_mbc(rom.clone(), false).err(),
+            Some(error::ROM_SIZE_MISMATCH)
+        );
+        assert!(super::get_mbc(rom, true).is_ok());
+
+        // 4MiB declared, more than MBC1 can address
+        let rom = testrom::cartridge(0x01, 0x07, 0x00, &[]);
+        assert!(super::get_mbc(rom.clone(), false).is_err());
+        assert!(super::get_mbc(rom, true).is_ok());
+        let rom = testrom::cartridge(0x19, 0x07, 0x00, &[]);
+        assert!(super::get_mbc(rom, false).is_ok());
+    }
+
+    #[test]
+    fn rtc_roundtrip() {
+        let rom = testrom::cartridge(0x10, 0x00, 0x02, &[]);
+        let mut mbc = super::get_mbc(rom.clone(), false).unwrap();
+        mbc.writerom(0x0000, 0x0A);
+        // Halt the clock so it holds the values written
+        mbc.writerom(0x4000, 0x0C);
+        mbc.writeram(0xA000, 0x40);
+        for &(reg, v) in &[(0x08, 30), (0x09, 15), (0x0A, 5), (0x0B, 200)] {
+            mbc.writerom(0x4000, reg);
+            mbc.writeram(0xA000, v);
+        }
+        mbc.writerom(0x6000, 0x00);
+        mbc.writerom(0x6000, 0x01);
+        let rtc = mbc.dump_rtc().unwrap();
+
+        let mut other = super::get_mbc(rom, false).unwrap();
+        other.load_rtc(&rtc).unwrap();
+        assert_eq!(other.dump_rtc(), Some(rtc));
+        assert_eq!(other.rtc_seconds(), mbc.rtc_seconds());
+        other.writerom(0x0000, 0x0A);
+        for &(reg, v) in &[(0x08, 30), (0x09, 15), (0x0A, 5), (0x0B, 200), (0x0C, 0x40)] {
+            other.writerom(0x4000, reg);
+            assert_eq!(other.readram(0xA000), v);
+        }
+        assert!(other.load_rtc(&[0; 4]).is_err());
+
+        let mut plain = super::get_mbc(testrom::cartridge(0x13, 0x00, 0x02, &[]), false).unwrap();
+        assert_eq!(plain.dump_rtc(), None);
+        assert_eq!(plain.load_rtc(&[0; 18]), Err(error::NO_CLOCK));
+    }
+
     #[test]
     fn checksum_zero() {
         let mut data = vec![0; 0x150];
@@ -0,0 +1,185 @@
+use crate::layout::PokemonGenTwoLayout as Layout;
+use crate::mmu::{decode_bcd, MIRROR_PARTY_START, MIRROR_SIZE, MIRROR_VISIBLE_SIZE};
+use crate::StrResult;
+use serde::{Deserialize, Serialize};
+
+/// How a field is stored in the mirror.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum FieldEncoding {
+    /// The bytes as the game stores them: little endian numbers, single bytes or raw blobs.
+    LittleEndian,
+    /// A big endian number, byte swapped so the mirror holds it little endian.
+    BigEndian,
+    /// Packed BCD digits, most significant byte first, stored as a 4 byte little endian u32.
+    Bcd,
+}
+
+/// One field of the mirror: `width` bytes read from the CPU address `addr`, written at
+/// `offset` in the mirror.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MirrorField {
+    pub offset: usize,
+    pub addr: u16,
+    pub width: usize,
+    pub encoding: FieldEncoding,
+}
+
+impl MirrorField {
+    pub fn new(offset: usize, addr: u16, width: usize, encoding: FieldEncoding) -> MirrorField {
+        MirrorField {
+            offset,
+            addr,
+            width,
+            encoding,
+        }
+    }
+
+    /// Number of mirror bytes the field takes.
+    pub fn mirror_len(&self) -> usize {
+        match self.encoding {
+            FieldEncoding::Bcd => 4,
+            _ => self.width,
+        }
+    }
+
+    fn reads(&self, addr: u16) -> bool {
+        addr >= self.addr && ((addr - self.addr) as usize) < self.width
+    }
+}
+
+/// Which game memory the mirror is built from. The first 4 bytes of every mirror hold the
+/// frame counter (u32 LE), the fields go anywhere after it. Bytes that no field covers stay 0.
+/// Only the first `visible_size` bytes are handed out unless the debug region is enabled.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct MirrorSpec {
+    pub size: usize,
+    pub visible_size: usize,
+    pub fields: Vec<MirrorField>,
+}
+
+/// Mirror bytes taken by the frame counter.
+const FRAME_COUNTER_SIZE: usize = 4;
+
+impl MirrorSpec {
+    /// A spec with all `size` bytes visible.
+    pub fn new(size: usize, fields: Vec<MirrorField>) -> MirrorSpec {
+        MirrorSpec {
+            size,
+            visible_size: size,
+            fields,
+        }
+    }
+
+    /// Pokemon Gold/Silver, the layout documented in `mmu.rs`.
+    pub fn pokemon_gold_silver() -> MirrorSpec {
+        use FieldEncoding::*;
+        let field = MirrorField::new;
+        let mut fields = vec![
+            field(0x004, 0xDA00, 2, LittleEndian), // map bank, map ID
+            field(0x006, 0xD20D, 2, LittleEndian), // player X, Y
+            field(0x008, Layout::PARTY_COUNT, 1, LittleEndian),
+        ];
+        // Each party slot picks its fields out of the game's 48 byte party_struct
+        for slot in 0..Layout::PARTY_MAX {
+            let offset = MIRROR_PARTY_START + slot * Layout::MIRROR_PARTY_SLOT_SIZE;
+            let mon = Layout::PARTY_MONS + slot as u16 * Layout::PARTY_MON_SIZE;
+            fields.extend_from_slice(&[
+                field(offset, mon + Layout::PARTY_MON_SPECIES, 1, LittleEndian),
+                field(offset + 1, mon + Layout::PARTY_MON_LEVEL, 1, LittleEndian),
+                field(offset + 2, mon + Layout::PARTY_MON_HP, 2, BigEndian),
+                field(offset + 4, mon + Layout::PARTY_MON_MAX_HP, 2, BigEndian),
+                field(offset + 6, mon + Layout::PARTY_MON_STATUS, 1, LittleEndian),
+                field(offset + 7, mon + Layout::PARTY_MON_MOVES, 4, LittleEndian),
+            ]);
+        }
+        fields.extend_from_slice(&[
+            field(0x049, 0xD116, 1, LittleEndian), // in battle
+            field(0x04A, 0xD0ED, 1, LittleEndian), // enemy species
+            field(0x04B, 0xD0FC, 1, LittleEndian), // enemy level
+            field(0x04C, 0xD0FF, 2, BigEndian),    // enemy HP
+            field(0x04E, 0xD101, 2, BigEndian),    // enemy max HP
+            field(0x050, 0xD573, 3, Bcd),          // money
+            field(0x054, 0xD57C, 1, LittleEndian), // badges
+            field(0x058, 0xFFD3, 2, LittleEndian), // RNG (debug)
+        ]);
+        MirrorSpec {
+            size: MIRROR_SIZE,
+            visible_size: MIRROR_VISIBLE_SIZE,
+            fields,
+        }
+    }
+
+    /// Check that every field fits in the mirror beside the frame counter.
+    pub fn validate(&self) -> StrResult<()> {
+        if self.size < FRAME_COUNTER_SIZE || self.visible_size > self.size {
+            return Err("Mirror spec sizes are invalid");
+        }
+        for f in &self.fields {
+            let fits = f.offset >= FRAME_COUNTER_SIZE && f.offset + f.mirror_len() <= self.size;
+            let bcd_fits = f.encoding != FieldEncoding::Bcd || f.width <= 4;
+            if !fits || !bcd_fits || f.width == 0 || f.addr as usize + f.width > 0x10000 {
+                return Err("Mirror spec field does not fit");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any field is read from `addr`.
+    pub fn reads(&self, addr: u16) -> bool {
+        self.fields.iter().any(|f| f.reads(addr))
+    }
+
+    /// Fill the fields of `mirror`, reading game memory with `read`.
+    pub fn write(&self, mirror: &mut [u8], mut read: impl FnMut(u16) -> u8) {
+        for f in &self.fields {
+            let mut bytes: Vec<u8> = (0..f.width).map(|i| read(f.addr + i as u16)).collect();
+            match f.encoding {
+                FieldEncoding::LittleEndian => {}
+                FieldEncoding::BigEndian => bytes.reverse(),
+                FieldEncoding::Bcd => bytes = decode_bcd(&bytes).to_le_bytes().to_vec(),
+            }
+            mirror[f.offset..f.offset + f.mirror_len()].copy_from_slice(&bytes);
+        }
+    }
+}
+
+impl Default for MirrorSpec {
+    fn default() -> MirrorSpec {
+        MirrorSpec::pokemon_gold_silver()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FieldEncoding, MirrorField, MirrorSpec};
+
+    #[test]
+    fn validate() {
+        assert!(MirrorSpec::pokemon_gold_silver().validate().is_ok());
+        let field = |offset, width, encoding| MirrorField::new(offset, 0xC000, width, encoding);
+        let spec = |fields| MirrorSpec::new(8, fields);
+        assert!(spec(vec![field(4, 4, FieldEncoding::LittleEndian)])
+            .validate()
+            .is_ok());
+        assert!(spec(vec![field(2, 1, FieldEncoding::LittleEndian)])
+            .validate()
+            .is_err());
+        assert!(spec(vec![field(6, 1, FieldEncoding::Bcd)])
+            .validate()
+            .is_err());
+        assert!(spec(vec![field(4, 5, FieldEncoding::Bcd)])
+            .validate()
+            .is_err());
+        assert!(MirrorSpec::new(2, vec![]).validate().is_err());
+    }
+
+    #[test]
+    fn reads() {
+        let spec = MirrorSpec::pokemon_gold_silver();
+        // Last party member's max HP, but not the stats after it
+        assert!(spec.reads(0xDA2A + 5 * 48 + 0x25));
+        assert!(!spec.reads(0xDA2A + 5 * 48 + 0x26));
+        assert!(spec.reads(0xD575));
+        assert!(!spec.reads(0xC000));
+    }
+}
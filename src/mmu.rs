@@ -1,7 +1,10 @@
+use crate::cheat::{self, Cheat};
+use crate::error;
 use crate::gbmode::{GbMode, GbSpeed};
 use crate::gpu::GPU;
 use crate::keypad::Keypad;
 use crate::mbc;
+use crate::mirror::MirrorSpec;
 use crate::serial::{Serial, SerialCallback};
 use crate::sound::Sound;
 use crate::timer::Timer;
@@ -13,20 +16,13 @@ const ZRAM_SIZE: usize = 0x7F;
 
 // Custom
 // Pokemon G/S Memory
-pub const MIRROR_FRAME_COUNTER: usize = 0x000;
-pub const MIRROR_MAP_BANK: usize = 0x004;
-pub const MIRROR_MAP_ID: usize = 0x005;
-pub const MIRROR_PLAYER_X: usize = 0x006;
-pub const MIRROR_PLAYER_Y: usize = 0x007;
 pub const MIRROR_PARTY_COUNT: usize = 0x008;
 pub const MIRROR_PARTY_START: usize = 0x009; // 6 × 11 bytes = 66 bytes
 pub const MIRROR_IN_BATTLE: usize = 0x049;
 pub const MIRROR_ENEMY_SPECIES: usize = 0x04A;
-pub const MIRROR_ENEMY_LEVEL: usize = 0x04B;
 pub const MIRROR_ENEMY_HP: usize = 0x04C; // 2 bytes
-pub const MIRROR_MONEY: usize = 0x04E; // 3 bytes
-pub const MIRROR_BADGES: usize = 0x051;
-pub const MIRROR_SIZE: usize = 0x052; // 80 bytes
+pub const MIRROR_MONEY: usize = 0x050; // 4 bytes
+pub const MIRROR_BADGES: usize = 0x054;
 
 /* 
 Mirror snapshot layout (little-endian) — for writing to fixed WRAM mirror region (e.g. 0xC000).
@@ -44,7 +40,8 @@ Layout (offsets are mirror-relative):
  0x006  1  Player X             -> u8
  0x007  1  Player Y             -> u8
  0x008  1  Party count          -> u8
- 0x009 66  Party slots (6×11)   -> per slot: species, level, cur HP, max HP, status, moves (mostly u8)
+ 0x009 66  Party slots (6×11)   -> per slot: species, level, cur HP, max HP, status, moves (HP u16 LE),
+                                   picked from the 48 byte party_struct of each member
  0x049  1  In battle            -> u8 (non-zero = in battle)
  0x04A  1  Enemy species        -> u8
  0x04B  1  Enemy level          -> u8
@@ -55,14 +52,18 @@ Layout (offsets are mirror-relative):
  0x055  3  Padding / reserved   -> reserved for future expansion
  0x058 16  Hidden / debug       -> optional: RNG, IVs, internal flags, etc.
 
-Total size: 0x068 (104 bytes)
+Total size: 0x068 (104 bytes), MIRROR_SIZE. The visible part is 0x058 bytes, MIRROR_VISIBLE_SIZE.
 
 Notes:
  - Keep the frame counter increment and mirror write atomic in MMU::write_mirror().
  - Expose MIRROR_SIZE and offsets as pub constants so Python/agents don't hardcode numbers.
  - For fairness, default bindings should expose only this "visible" mirror. Hidden/debug fields may be appended after MIRROR_SIZE or exposed via a flag.
 */
-pub const MIRROR_SIZE: usize = 0x068
+/// Size of the whole mirror, including the hidden/debug region.
+pub const MIRROR_SIZE: usize = 0x068;
+/// Size of the player-observable part of the mirror, everything before the hidden/debug
+/// region.
+pub const MIRROR_VISIBLE_SIZE: usize = 0x058;
 
 #[derive(PartialEq, Serialize, Deserialize)]
 enum DMAType {
@@ -95,13 +96,101 @@ pub struct MMU {
     pub gbmode: GbMode,
     gbspeed: GbSpeed,
     speed_switch_req: bool,
+    /// Cycles left of the speed switch in progress, 0 when none is.
+    #[serde(default)]
+    speed_switch_ticks: u32,
     undocumented_cgb_regs: [u8; 3], // 0xFF72, 0xFF73, 0xFF75
 
     // Custom
-    wram_mirror: [u8; MIRROR_SIZE],
+    #[serde(default = "empty_mirror")]
+    mirror: Vec<u8>,
+    #[serde(default)]
+    mirror_spec: MirrorSpec,
+    #[serde(default)]
     frame_counter: u32,
+    #[serde(default)]
+    total_cycles: u64,
+    #[serde(default)]
+    mirror_debug: bool,
+    #[serde(default)]
+    mirror_wram_base: Option<u16>,
+    #[serde(default)]
+    mirror_write_tracking: bool,
+    #[serde(default)]
+    mirror_source_written: bool,
+    #[serde(default)]
+    mirror_fresh: bool,
+    /// Set when a CGB only register was written outside of `Color` mode.
+    #[serde(default)]
+    cgb_write_blocked: bool,
+    /// Number of HDMA transfers ignored for an invalid source address.
+    #[serde(default)]
+    hdma_errors: u64,
+    /// Seed WRAM was last filled from, so `reset` restores the same contents.
+    #[serde(default = "default_wram_seed")]
+    wram_seed: Option<u32>,
+    /// Copy OAM DMA one byte per machine cycle instead of at once, see `set_oam_dma_timing`.
+    #[serde(default)]
+    oamdma_timing: bool,
+    #[serde(default)]
+    oamdma_src: u16,
+    /// Cycles left of the OAM DMA in progress, 0 when none is.
+    #[serde(default)]
+    oamdma_ticks: u32,
+    #[serde(default)]
+    cheats: Vec<Cheat>,
+    #[serde(skip)]
+    watchpoints: Vec<u16>,
+    /// The first watched address written since `take_watchpoint_hit`.
+    #[serde(skip)]
+    watchpoint_hit: Option<u16>,
+    #[serde(skip)]
+    pub rumble_callback: Option<Box<dyn mbc::RumbleCallback>>,
+}
+
+/// Cycles the CPU pauses for a speed switch, 2050 machine cycles.
+const SPEED_SWITCH_TICKS: u32 = 2050 * 4;
+
+/// Cycles an OAM DMA takes: one byte of the 0xA0 per machine cycle.
+const OAMDMA_TICKS: u32 = 0xA0 * 4;
+
+/// `address` with the echo of WRAM mapped back onto WRAM.
+fn unecho(address: u16) -> u16 {
+    match address {
+        0xE000..=0xFDFF => address - 0x2000,
+        _ => address,
+    }
+}
+
+/// Decode packed BCD, most significant byte first: two decimal digits per byte, high nibble
+/// first. [0x12, 0x34, 0x56] is 123456.
+pub fn decode_bcd(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, &b| {
+        value * 100 + (b >> 4) as u32 * 10 + (b & 0x0F) as u32
+    })
+}
+
+/// Check that `size` mirror bytes copied to `base` stay within WRAM.
+fn check_mirror_wram_range(base: Option<u16>, size: usize) -> StrResult<()> {
+    match base {
+        Some(base) if base < 0xC000 || base as usize + size > 0xE000 => {
+            Err("Mirror does not fit in WRAM at this address")
+        }
+        _ => Ok(()),
+    }
+}
+
+fn default_wram_seed() -> Option<u32> {
+    Some(DEFAULT_WRAM_SEED)
+}
+
+fn empty_mirror() -> Vec<u8> {
+    vec![0; MIRROR_SIZE]
 }
 
+/// Seed of the pseudo random WRAM contents at power on, see `MMU::init_wram`.
+pub const DEFAULT_WRAM_SEED: u32 = 42;
+
 fn fill_random(slice: &mut [u8], start: u32) {
     // Simple LCG to generate (non-cryptographic) random values
     // Each distinct invocation should use a different start value
@@ -140,15 +229,35 @@ impl MMU {
             gbmode: GbMode::Classic,
             gbspeed: GbSpeed::Single,
             speed_switch_req: false,
+            speed_switch_ticks: 0,
             hdma_src: 0,
             hdma_dst: 0,
             hdma_status: DMAType::NoDMA,
             hdma_len: 0xFF,
             undocumented_cgb_regs: [0; 3],
+            mirror: empty_mirror(),
+            mirror_spec: MirrorSpec::default(),
+            frame_counter: 0,
+            total_cycles: 0,
+            mirror_debug: false,
+            mirror_wram_base: None,
+            mirror_write_tracking: false,
+            mirror_source_written: false,
+            mirror_fresh: false,
+            cgb_write_blocked: false,
+            hdma_errors: 0,
+            wram_seed: Some(DEFAULT_WRAM_SEED),
+            oamdma_timing: false,
+            oamdma_src: 0,
+            oamdma_ticks: 0,
+            cheats: Vec::new(),
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            rumble_callback: None,
         };
-        fill_random(&mut res.wram, 42);
+        res.init_wram(Some(DEFAULT_WRAM_SEED));
         if res.rb(0x0143) == 0xC0 {
-            return Err("This game does not work in Classic mode");
+            return Err(error::CLASSIC_MODE_UNSUPPORTED);
         }
         res.set_initial();
         Ok(res)
@@ -178,13 +287,33 @@ impl MMU {
             gbmode: GbMode::Color,
             gbspeed: GbSpeed::Single,
             speed_switch_req: false,
+            speed_switch_ticks: 0,
             hdma_src: 0,
             hdma_dst: 0,
             hdma_status: DMAType::NoDMA,
             hdma_len: 0xFF,
             undocumented_cgb_regs: [0; 3],
+            mirror: empty_mirror(),
+            mirror_spec: MirrorSpec::default(),
+            frame_counter: 0,
+            total_cycles: 0,
+            mirror_debug: false,
+            mirror_wram_base: None,
+            mirror_write_tracking: false,
+            mirror_source_written: false,
+            mirror_fresh: false,
+            cgb_write_blocked: false,
+            hdma_errors: 0,
+            wram_seed: Some(DEFAULT_WRAM_SEED),
+            oamdma_timing: false,
+            oamdma_src: 0,
+            oamdma_ticks: 0,
+            cheats: Vec::new(),
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            rumble_callback: None,
         };
-        fill_random(&mut res.wram, 42);
+        res.init_wram(Some(DEFAULT_WRAM_SEED));
         res.determine_mode();
         res.set_initial();
         Ok(res)
@@ -213,6 +342,11 @@ impl MMU {
         self.wb(0xFF24, 0x77);
         self.wb(0xFF25, 0xF3);
         self.wb(0xFF26, 0xF1);
+        self.set_initial_gpu();
+    }
+
+    /// LCD registers as left behind by the boot ROM.
+    fn set_initial_gpu(&mut self) {
         self.wb(0xFF40, 0x91);
         self.wb(0xFF42, 0);
         self.wb(0xFF43, 0);
@@ -233,11 +367,72 @@ impl MMU {
         self.gpu.gbmode = mode;
     }
 
+    /// Fill WRAM as at power on: pseudo random bytes from `seed`, the same for every seed and
+    /// version, or zeros with `None`. Real hardware starts with garbage.
+    pub fn init_wram(&mut self, seed: Option<u32>) {
+        self.wram_seed = seed;
+        match seed {
+            Some(seed) => fill_random(&mut self.wram, seed),
+            None => self.wram.fill(0),
+        }
+    }
+
+    /// Whether the game wrote a CGB only register, which is ignored outside of `Color` mode.
+    pub fn cgb_write_blocked(&self) -> bool {
+        self.cgb_write_blocked
+    }
+
+    /// Number of HDMA transfers ignored because their source address was invalid.
+    pub fn hdma_errors(&self) -> u64 {
+        self.hdma_errors
+    }
+
+    /// Take 160 machine cycles for an OAM DMA, during which the CPU can only read HRAM, as on
+    /// hardware. Off by default: the DMA completes as soon as FF46 is written, which is faster
+    /// and what games waiting in HRAM cannot tell apart.
+    pub fn set_oam_dma_timing(&mut self, enabled: bool) {
+        self.oamdma_timing = enabled;
+        self.finish_oamdma();
+    }
+
+    /// Whether an OAM DMA is in progress.
+    pub fn oamdma_active(&self) -> bool {
+        self.oamdma_ticks > 0
+    }
+
+    /// Put the GPU back in its state after the boot ROM, keeping emulator options such as
+    /// rendering and the layer mask.
+    pub fn reset_gpu(&mut self) {
+        self.gpu.reset();
+        self.set_initial_gpu();
+    }
+
+    pub fn reset_timer(&mut self) {
+        self.timer = Timer::new();
+    }
+
+    /// Put the APU, if audio is enabled, back in the state `Device::enable_audio` creates.
+    pub fn reset_sound(&mut self) {
+        self.sound = self.sound.take().map(Sound::reset);
+    }
+
     pub fn do_cycle(&mut self, ticks: u32) -> u32 {
         let cpudivider = self.gbspeed as u32;
+        let switching = self.speed_switching();
+        if switching {
+            self.do_speed_switch(ticks);
+        }
         let vramticks = self.perform_vramdma();
-        let gputicks = ticks / cpudivider + vramticks;
+        // The LCD stands still during a speed switch
+        let gputicks = if switching {
+            0
+        } else {
+            ticks / cpudivider + vramticks
+        };
         let cputicks = ticks + vramticks * cpudivider;
+        self.total_cycles += cputicks as u64;
+
+        self.do_oamdma(cputicks);
 
         self.timer.do_cycle(cputicks);
         self.intf |= self.timer.interrupt;
@@ -252,6 +447,7 @@ impl MMU {
 
         let _ = self.sound.as_mut().map_or((), |s| s.do_cycle(gputicks));
 
+        self.serial.do_cycle(cputicks);
         self.intf |= self.serial.interrupt;
         self.serial.interrupt = 0;
 
@@ -260,6 +456,17 @@ impl MMU {
 
     pub fn rb(&mut self, address: u16) -> u8 {
         match address {
+            0xFF80..=0xFFFE => self.read_bus(address),
+            _ if self.oamdma_active() => 0xFF,
+            _ => self.read_bus(address),
+        }
+    }
+
+    fn read_bus(&mut self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF if !self.cheats.is_empty() => {
+                cheat::patch_rom(&self.cheats, address, self.mbc.readrom(address))
+            }
             0x0000..=0x7FFF => self.mbc.readrom(address),
             0x8000..=0x9FFF => self.gpu.rb(address),
             0xA000..=0xBFFF => self.mbc.readram(address),
@@ -292,7 +499,7 @@ impl MMU {
             0xFF70 => self.wrambank as u8,
             0xFF72..=0xFF73 => self.undocumented_cgb_regs[address as usize - 0xFF72],
             0xFF75 => self.undocumented_cgb_regs[2] | 0b10001111,
-            0xFF76..=0xFF77 => 0x00, // CGB PCM registers. Not yet implemented.
+            0xFF76..=0xFF77 => self.sound.as_mut().map_or(0x00, |s| s.rb(address)),
             0xFF80..=0xFFFE => self.zram[address as usize & 0x007F],
             0xFFFF => self.inte,
             _ => 0xFF,
@@ -304,8 +511,30 @@ impl MMU {
     }
 
     pub fn wb(&mut self, address: u16, value: u8) {
+        let value = if self.cheats.is_empty() {
+            value
+        } else {
+            cheat::force_ram(&self.cheats, address, value)
+        };
+        if !self.watchpoints.is_empty()
+            && self.watchpoint_hit.is_none()
+            && self.watchpoints.contains(&address)
+        {
+            self.watchpoint_hit = Some(address);
+        }
+        if self.mirror_write_tracking && self.mirror_spec.reads(unecho(address)) {
+            self.mirror_source_written = true;
+        }
         match address {
-            0x0000..=0x7FFF => self.mbc.writerom(address, value),
+            0x0000..=0x7FFF => {
+                let rumble = self.mbc.rumble();
+                self.mbc.writerom(address, value);
+                if self.mbc.rumble() != rumble {
+                    if let Some(cb) = self.rumble_callback.as_mut() {
+                        cb.call(!rumble);
+                    }
+                }
+            }
             0x8000..=0x9FFF => self.gpu.wb(address, value),
             0xA000..=0xBFFF => self.mbc.writeram(address, value),
             0xC000..=0xCFFF | 0xE000..=0xEFFF => self.wram[address as usize & 0x0FFF] = value,
@@ -318,8 +547,11 @@ impl MMU {
             0xFF04..=0xFF07 => self.timer.wb(address, value),
             0xFF10..=0xFF3F => self.sound.as_mut().map_or((), |s| s.wb(address, value)),
             0xFF46 => self.oamdma(value),
-            0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF6C | 0xFF70 | 0xFF76..=0xFF77
-                if self.gbmode != GbMode::Color => {}
+            0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF68..=0xFF6C | 0xFF70 | 0xFF76..=0xFF77
+                if self.gbmode != GbMode::Color =>
+            {
+                self.cgb_write_blocked = true
+            }
             0xFF72..=0xFF73 | 0xFF75..=0xFF77 if self.gbmode == GbMode::Classic => {}
             0xFF4D => {
                 if value & 0x1 == 0x1 {
@@ -349,25 +581,70 @@ impl MMU {
         self.wb(address + 1, (value >> 8) as u8);
     }
 
-    pub fn switch_speed(&mut self) {
-        if self.speed_switch_req {
-            if self.gbspeed == GbSpeed::Double {
-                self.gbspeed = GbSpeed::Single;
-            } else {
-                self.gbspeed = GbSpeed::Double;
-            }
+    /// Start a speed switch if one was requested through FF4D, returning whether it did. The
+    /// CPU pauses and the LCD is blank for `SPEED_SWITCH_TICKS`, then the new speed applies.
+    pub fn switch_speed(&mut self) -> bool {
+        let requested = self.speed_switch_req;
+        if requested {
+            self.speed_switch_ticks = SPEED_SWITCH_TICKS;
+            self.gpu.blank_screen();
         }
         self.speed_switch_req = false;
+        requested
+    }
+
+    /// CPU cycles per second at the current speed.
+    pub fn clock_hz(&self) -> u32 {
+        match self.gbspeed {
+            GbSpeed::Single => crate::CLOCK_HZ,
+            GbSpeed::Double => crate::CLOCK_HZ_DOUBLE_SPEED,
+        }
+    }
+
+    /// Whether a speed switch is in progress, during which the CPU does nothing.
+    pub fn speed_switching(&self) -> bool {
+        self.speed_switch_ticks > 0
+    }
+
+    fn do_speed_switch(&mut self, ticks: u32) {
+        self.speed_switch_ticks = self.speed_switch_ticks.saturating_sub(ticks);
+        if self.speed_switch_ticks == 0 {
+            self.gbspeed = match self.gbspeed {
+                GbSpeed::Single => GbSpeed::Double,
+                GbSpeed::Double => GbSpeed::Single,
+            };
+        }
     }
 
     fn oamdma(&mut self, value: u8) {
-        let base = (value as u16) << 8;
-        for i in 0..0xA0 {
-            let b = self.rb(base + i);
+        self.oamdma_src = (value as u16) << 8;
+        self.oamdma_ticks = OAMDMA_TICKS;
+        if !self.oamdma_timing {
+            self.finish_oamdma();
+        }
+    }
+
+    /// Bytes of the current OAM DMA already copied.
+    fn oamdma_copied(&self) -> u16 {
+        ((OAMDMA_TICKS - self.oamdma_ticks) / 4) as u16
+    }
+
+    fn do_oamdma(&mut self, ticks: u32) {
+        if !self.oamdma_active() {
+            return;
+        }
+        let start = self.oamdma_copied();
+        self.oamdma_ticks = self.oamdma_ticks.saturating_sub(ticks);
+        for i in start..self.oamdma_copied() {
+            let b = self.read_bus(self.oamdma_src + i);
             self.wb(0xFE00 + i, b);
         }
     }
 
+    fn finish_oamdma(&mut self) {
+        self.do_oamdma(OAMDMA_TICKS);
+    }
+
     fn hdma_read(&self, a: u16) -> u8 {
         match a {
             0xFF51..=0xFF54 => self.hdma[(a - 0xFF51) as usize],
@@ -399,7 +676,8 @@ impl MMU {
                 let src = ((self.hdma[0] as u16) << 8) | (self.hdma[1] as u16);
                 let dst = ((self.hdma[2] as u16) << 8) | (self.hdma[3] as u16) | 0x8000;
                 if !(src <= 0x7FF0 || (src >= 0xA000 && src <= 0xDFF0)) {
-                    panic!("HDMA transfer with illegal start address {:04X}", src);
+                    self.hdma_errors += 1;
+                    return;
                 }
 
                 self.hdma_src = src;
@@ -447,6 +725,25 @@ impl MMU {
         return len * 8;
     }
 
+    /// Finish a running GDMA or HDMA right away, e.g. to take a save state at a point where
+    /// no transfer is in flight. The CPU is not stalled for the copied rows.
+    pub fn complete_pending_dma(&mut self) {
+        match self.hdma_status {
+            DMAType::NoDMA => {}
+            DMAType::GDMA => {
+                self.perform_gdma();
+            }
+            DMAType::HDMA => {
+                while self.hdma_status == DMAType::HDMA {
+                    self.perform_vramdma_row();
+                    if self.hdma_len == 0x7F {
+                        self.hdma_status = DMAType::NoDMA;
+                    }
+                }
+            }
+        }
+    }
+
     fn perform_vramdma_row(&mut self) {
         let mmu_src = self.hdma_src;
         for j in 0..0x10 {
@@ -463,73 +760,476 @@ impl MMU {
         }
     }
 
+    /// Write `value` at `address` the way a hardware fault would: WRAM and cartridge RAM are
+    /// changed even when the game has the RAM disabled, and without watchers like the mirror
+    /// write tracking noticing. Other addresses are written with `wb`.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0xA000..=0xBFFF => self.mbc.poke_ram(address, value),
+            0xC000..=0xCFFF | 0xE000..=0xEFFF => self.wram[address as usize & 0x0FFF] = value,
+            0xD000..=0xDFFF | 0xF000..=0xFDFF => {
+                self.wram[(self.wrambank * 0x1000) | (address as usize & 0x0FFF)] = value
+            }
+            _ => self.wb(address, value),
+        }
+    }
+
+    /// Read WRAM (or its echo) at CPU address `gb_addr` with the same bank mapping as `rb`,
+    /// but without side effects. Other addresses read as 0xFF.
+    fn read_wram(&self, gb_addr: u16) -> u8 {
+        match gb_addr {
+            0xC000..=0xCFFF | 0xE000..=0xEFFF => self.wram[gb_addr as usize & 0x0FFF],
+            0xD000..=0xDFFF | 0xF000..=0xFDFF => {
+                self.wram[(self.wrambank * 0x1000) | gb_addr as usize & 0x0FFF]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Like `read_wram`, but HRAM can be read as well.
+    fn read_mirror_source(&self, gb_addr: u16) -> u8 {
+        match gb_addr {
+            0xFF80..=0xFFFE => self.zram[gb_addr as usize & 0x007F],
+            _ => self.read_wram(gb_addr),
+        }
+    }
+
     // Custom
     pub fn write_mirror(&mut self) {
-        // --- frame counter ---
+        self.mirror_fresh = self.mirror_source_written;
+
         self.frame_counter = self.frame_counter.wrapping_add(1);
+        self.refresh_mirror();
+
+        // --- optional copy into WRAM, range checked by set_mirror_wram_base ---
+        if let Some(base) = self.mirror_wram_base {
+            for (i, &v) in self.mirror.iter().enumerate() {
+                let address = base as usize + i;
+                let index = match address {
+                    0xC000..=0xCFFF => address & 0x0FFF,
+                    _ => (self.wrambank * 0x1000) | (address & 0x0FFF),
+                };
+                self.wram[index] = v;
+            }
+        }
+        self.mirror_source_written = false;
+    }
+
+    /// Rebuild the mirror from the current memory without counting a frame.
+    pub fn refresh_mirror(&mut self) {
+        // --- frame counter ---
         self.mirror[0x000..0x004].copy_from_slice(&self.frame_counter.to_le_bytes());
 
-        // --- map & player ---
-        self.mirror[0x004] = self.wram[0xDA00]; // map bank
-        self.mirror[0x005] = self.wram[0xDA01]; // map ID
-        self.mirror[0x006] = self.wram[0xD20D]; // X
-        self.mirror[0x007] = self.wram[0xD20E]; // Y
+        // --- game fields, Pokemon G/S unless set_mirror_spec chose others ---
+        let mut mirror = std::mem::take(&mut self.mirror);
+        self.mirror_spec
+            .write(&mut mirror, |a| self.read_mirror_source(a));
+        self.mirror = mirror;
+    }
 
-        // --- party ---
-        self.mirror[0x008] = self.wram[0xDA22]; // party count
-        for i in 0..6 {
-            let src = 0xDA2A + i*11;
-            let dst = 0x009 + i*11;
-            self.mirror[dst..dst+11].copy_from_slice(&self.wram[src..src+11]);
-        }
+    /// Debug aid: record whether the game writes any of the addresses the mirror is built
+    /// from, see `mirror_is_fresh`. Every memory write is checked, so this is off by default.
+    pub fn set_mirror_write_tracking(&mut self, enabled: bool) {
+        self.mirror_write_tracking = enabled;
+        self.mirror_source_written = false;
+        self.mirror_fresh = false;
+    }
 
-        // --- battle state ---
-        self.mirror[0x049] = self.wram[0xD116];      // in battle
-        self.mirror[0x04A] = self.wram[0xD0ED];      // enemy species
-        self.mirror[0x04B] = self.wram[0xD0FC];      // enemy level
+    /// Whether a source address of the mirror was written between the last two
+    /// `write_mirror` calls, i.e. whether the current mirror may differ from the one before.
+    /// Always true without `set_mirror_write_tracking`, as nothing is known then.
+    pub fn mirror_is_fresh(&self) -> bool {
+        !self.mirror_write_tracking || self.mirror_fresh
+    }
 
-        let enemy_cur_hp = u16::from_be_bytes([self.wram[0xD0FF], self.wram[0xD100]]);
-        self.mirror[0x04C..0x04E].copy_from_slice(&enemy_cur_hp.to_le_bytes());
+    /// Also copy the mirror into WRAM at `base` on every VBlank, so code running on the
+    /// emulated CPU can read it. The copy overwrites whatever the game keeps there, so pick
+    /// a region the game does not use. `None` (the default) only fills the internal buffer.
+    /// Fails unless the whole mirror fits in WRAM (0xC000-0xDFFF).
+    pub fn set_mirror_wram_base(&mut self, base: Option<u16>) -> StrResult<()> {
+        check_mirror_wram_range(base, self.mirror.len())?;
+        self.mirror_wram_base = base;
+        Ok(())
+    }
 
-        let enemy_max_hp = u16::from_be_bytes([self.wram[0xD101], self.wram[0xD102]]);
-        self.mirror[0x04E..0x050].copy_from_slice(&enemy_max_hp.to_le_bytes());
+    /// Number of mirror updates, i.e. VBlanks, so far. This is the frame counter at the
+    /// start of the mirror.
+    pub fn frame_counter(&self) -> u32 {
+        self.frame_counter
+    }
 
-        // --- money (3-byte BCD -> u32 LE) ---
-        let bcd = &self.wram[0xD573..0xD576];
-        let money = (bcd[0] as u32)*10000 + (bcd[1] as u32)*100 + (bcd[2] as u32);
-        self.mirror[0x050..0x054].copy_from_slice(&money.to_le_bytes());
+    /// CPU cycles (T-cycles) run so far, twice as many per frame in double speed mode.
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
 
-        // --- badges ---
-        self.mirror[0x054] = self.wram[0xD57C];
+    /// Apply `cheat` from now on. A GameShark code also writes its value right away.
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+        if let Cheat::GameShark { address, value, .. } = cheat {
+            self.wb(address, value);
+        }
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    /// Record writes to `address`, see `take_watchpoint_hit`. Echo RAM is watched apart from
+    /// the WRAM it mirrors.
+    pub fn add_watchpoint(&mut self, address: u16) {
+        if !self.watchpoints.contains(&address) {
+            self.watchpoints.push(address);
+        }
+    }
 
-        // --- padding / reserved ---
-        self.mirror[0x055..0x058].fill(0);
+    pub fn remove_watchpoint(&mut self, address: u16) {
+        self.watchpoints.retain(|&a| a != address);
+    }
+
+    /// The first watched address written since the last call, if any.
+    pub fn take_watchpoint_hit(&mut self) -> Option<u16> {
+        self.watchpoint_hit.take()
+    }
 
-        // --- optional hidden/debug ---
-        // Example: copy RNG state for debugging
-        self.mirror[0x058..0x05A].copy_from_slice(&self.wram[0xFFD3..0xFFD5]);
-        // remaining bytes (0x05A..0x068) can be used later for IVs, encounter cooldowns, etc.
+    /// Include the hidden/debug region (RNG and such) in `get_mirror`. Off by default, so
+    /// agents only see what a player could.
+    pub fn set_mirror_debug(&mut self, enabled: bool) {
+        self.mirror_debug = enabled;
     }
 
+    /// Build the mirror from other game memory than Pokemon Gold/Silver (`MirrorSpec`'s
+    /// default). The mirror is cleared and resized to the spec. Fails if the resized mirror
+    /// no longer fits in WRAM at the `set_mirror_wram_base` address.
+    pub fn set_mirror_spec(&mut self, spec: MirrorSpec) -> StrResult<()> {
+        spec.validate()?;
+        check_mirror_wram_range(self.mirror_wram_base, spec.size)?;
+        self.mirror = vec![0; spec.size];
+        self.mirror_spec = spec;
+        Ok(())
+    }
+
+    /// The visible part of the mirror (`MIRROR_VISIBLE_SIZE` bytes for Pokemon G/S), or all
+    /// of it with `set_mirror_debug`.
     pub fn get_mirror(&self) -> &[u8] {
-        &self.mirror[..MIRROR_SIZE]
+        match self.mirror_debug {
+            true => &self.mirror,
+            false => &self.mirror[..self.mirror_spec.visible_size],
+        }
     }
 
+    /// Return the hardware to its state after the boot ROM, keeping the mode, the cartridge
+    /// and the emulator options: mirror spec, cheats, watchpoints, audio output and serial and
+    /// rumble callbacks. WRAM is refilled from its seed and HRAM is cleared.
     pub fn reset(&mut self) {
-        // Clear WRAM/HRAM/VRAM/OAM to expected power-on values
-        for b in self.wram.iter_mut() { *b = 0; }
-        for b in self.vram.iter_mut() { *b = 0; }
-        for b in self.oam.iter_mut()  { *b = 0; }
-        // Reset IO registers to their default values (implement individually)
-        self.io_reset();
-        // Reset keypad state
-        self.keypad = crate::keypad::Keypad::new();
-        // Reset GPU and sound if needed (but GPU::new() will be called by CPU reset above)
-    }
-
-    fn io_reset(&mut self) {
-        // Write default power-on values for IO addresses if you want
-        // e.g. self.write_byte(0xFF00, 0xFF); // JOYP
-        // implement the small set of default IO registers your emulator requires
+        self.init_wram(self.wram_seed);
+        self.zram.fill(0);
+        self.mirror.fill(0);
+        self.hdma = [0; 4];
+        self.inte = 0;
+        self.intf = 0;
+        self.wrambank = 1;
+        self.hdma_status = DMAType::NoDMA;
+        self.hdma_src = 0;
+        self.hdma_dst = 0;
+        self.hdma_len = 0xFF;
+        self.hdma_errors = 0;
+        self.gbspeed = GbSpeed::Single;
+        self.speed_switch_req = false;
+        self.speed_switch_ticks = 0;
+        self.undocumented_cgb_regs = [0; 3];
+        self.frame_counter = 0;
+        self.total_cycles = 0;
+        self.mirror_source_written = false;
+        self.cgb_write_blocked = false;
+        self.oamdma_src = 0;
+        self.oamdma_ticks = 0;
+        self.watchpoint_hit = None;
+        self.serial.reset();
+        self.timer = Timer::new();
+        self.keypad = Keypad::new();
+        self.gpu.reset();
+        self.reset_sound();
+        self.set_initial();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MMU;
+    use crate::mbc;
+    use crate::testrom;
+
+    fn mmu(cgb: bool) -> MMU {
+        let mut rom = testrom::idle_rom();
+        if cgb {
+            rom[0x143] = 0x80;
+            testrom::fix_checksum(&mut rom);
+        }
+        let cart = mbc::get_mbc(rom, false).unwrap();
+        if cgb {
+            MMU::new_cgb(cart, None).unwrap()
+        } else {
+            MMU::new(cart, None).unwrap()
+        }
+    }
+
+    #[test]
+    fn init_wram() {
+        let mut a = mmu(false);
+        let b = mmu(false);
+        assert_eq!(&a.wram[..], &b.wram[..]);
+        a.init_wram(Some(7));
+        assert_ne!(&a.wram[..], &b.wram[..]);
+        a.init_wram(Some(super::DEFAULT_WRAM_SEED));
+        assert_eq!(&a.wram[..], &b.wram[..]);
+        a.init_wram(None);
+        assert!(a.wram.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn reset_keeps_wram_seed() {
+        for &seed in &[Some(7), None] {
+            let mut a = mmu(false);
+            a.init_wram(seed);
+            let seeded = a.wram;
+            a.wb(0xC123, 0x55);
+            a.reset();
+            assert_eq!(a.wram, seeded);
+        }
+    }
+
+    #[test]
+    fn decode_bcd() {
+        assert_eq!(super::decode_bcd(&[]), 0);
+        assert_eq!(super::decode_bcd(&[0x42]), 42);
+        assert_eq!(super::decode_bcd(&[0x01, 0x00]), 100);
+        assert_eq!(super::decode_bcd(&[0x12, 0x34, 0x56]), 123456);
+        assert_eq!(super::decode_bcd(&[0x99, 0x99, 0x99]), 999999);
+        assert_eq!(super::decode_bcd(&[0x00, 0x30, 0x05]), 3005);
+    }
+
+    #[test]
+    fn oam_dma_instant() {
+        let mut mmu = mmu(false);
+        mmu.wb(0xC010, 0x42);
+        mmu.wb(0xFF46, 0xC0);
+        assert!(!mmu.oamdma_active());
+        assert_eq!(mmu.rb(0xC010), 0x42);
+        assert_eq!(mmu.gpu.rb(0xFE10), 0x42);
+    }
+
+    #[test]
+    fn oam_dma_blocks_reads() {
+        let mut mmu = mmu(false);
+        mmu.set_oam_dma_timing(true);
+        for i in 0..0xA0 {
+            mmu.wb(0xC000 + i, i as u8 + 1);
+        }
+        mmu.wb(0xFF90, 0x77);
+        mmu.wb(0xFF46, 0xC0);
+
+        mmu.do_cycle(0x10 * 4);
+        assert!(mmu.oamdma_active());
+        assert_eq!(mmu.rb(0xC000), 0xFF);
+        assert_eq!(mmu.rb(0xFF90), 0x77);
+        assert_eq!(mmu.gpu.rb(0xFE0F), 0x10);
+        assert_eq!(mmu.gpu.rb(0xFE10), 0);
+
+        mmu.do_cycle(0x90 * 4);
+        assert!(!mmu.oamdma_active());
+        assert_eq!(mmu.rb(0xC000), 0x01);
+        assert_eq!(mmu.gpu.rb(0xFE9F), 0xA0);
+    }
+
+    #[test]
+    fn complete_pending_hdma() {
+        let mut mmu = mmu(true);
+        for i in 0..0x30 {
+            mmu.wb(0xC000 + i, i as u8 + 1);
+        }
+        mmu.wb(0xFF51, 0xC0);
+        mmu.wb(0xFF52, 0x00);
+        mmu.wb(0xFF53, 0x00);
+        mmu.wb(0xFF54, 0x00);
+        // HDMA of 3 rows of 16 bytes, copied one row per HBlank
+        mmu.wb(0xFF55, 0x82);
+        assert_eq!(mmu.rb(0xFF55) & 0x80, 0);
+
+        mmu.complete_pending_dma();
+        assert!(mmu.hdma_status == super::DMAType::NoDMA);
+        assert_eq!(mmu.rb(0xFF55), 0xFF);
+        for i in 0..0x30 {
+            assert_eq!(mmu.gpu.rb(0x8000 + i), i as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn echo_of_fixed_bank() {
+        let mut mmu = mmu(false);
+        mmu.wb(0xC123, 0x11);
+        assert_eq!(mmu.rb(0xE123), 0x11);
+        mmu.wb(0xEFFF, 0x22);
+        assert_eq!(mmu.rb(0xCFFF), 0x22);
+        mmu.wb(0xDDFF, 0x33);
+        assert_eq!(mmu.rb(0xFDFF), 0x33);
+    }
+
+    #[test]
+    fn echo_of_switchable_bank() {
+        let mut mmu = mmu(true);
+        for bank in 1..8 {
+            mmu.wb(0xFF70, bank);
+            mmu.wb(0xD456, bank * 3);
+            mmu.wb(0xFDFF, bank * 5);
+        }
+        for bank in 1..8 {
+            mmu.wb(0xFF70, bank);
+            assert_eq!(mmu.rb(0xF456), bank * 3);
+            assert_eq!(mmu.rb(0xDDFF), bank * 5);
+        }
+        // Bank 0 selects bank 1
+        mmu.wb(0xFF70, 0);
+        assert_eq!(mmu.rb(0xF456), 3);
+
+        // The echo ends at 0xFDFF, OAM follows
+        mmu.wb(0xDE00, 0x77);
+        mmu.wb(0xFE00, 0x12);
+        assert_eq!(mmu.rb(0xDE00), 0x77);
+    }
+
+    #[test]
+    fn mirror_write_tracking() {
+        let mut mmu = mmu(false);
+        mmu.write_mirror();
+        assert!(mmu.mirror_is_fresh());
+
+        mmu.set_mirror_write_tracking(true);
+        mmu.wb(0xC500, 1);
+        mmu.write_mirror();
+        assert!(!mmu.mirror_is_fresh());
+
+        mmu.wb(0xD20D, 5);
+        mmu.write_mirror();
+        assert!(mmu.mirror_is_fresh());
+        mmu.write_mirror();
+        assert!(!mmu.mirror_is_fresh());
+
+        // Echo RAM reaches the same bytes.
+        mmu.wb(0xFA00, 2);
+        mmu.write_mirror();
+        assert!(mmu.mirror_is_fresh());
+    }
+
+    #[test]
+    fn reset_clears_wram_and_mirror() {
+        let mmu_fresh = mmu(false);
+        let mut mmu = mmu(false);
+        mmu.wb(0xC123, 0x55);
+        mmu.wb(0x8000, 0xAA);
+        mmu.write_mirror();
+        assert!(mmu.get_mirror().iter().any(|&b| b != 0));
+
+        mmu.reset();
+        assert_eq!(mmu.wram, mmu_fresh.wram);
+        assert!(mmu.get_mirror().iter().all(|&b| b == 0));
+        assert_eq!(mmu.rb(0x8000), 0);
+    }
+
+    #[test]
+    fn write_mirror_reads_banked_wram() {
+        let mut mmu = mmu(true);
+        mmu.wb(0xFF70, 1);
+        mmu.wb(0xDA00, 3); // map bank
+        mmu.wb(0xDA01, 7); // map ID
+        mmu.wb(0xD20D, 12); // X
+        mmu.wb(0xD20E, 34); // Y
+        mmu.wb(0xDA22, 1); // party count
+        mmu.wb(0xDA2A, 155); // lead species
+        mmu.wb(0xDA2A + 0x1F, 5); // lead level
+        mmu.wb(0xDA2A + 0x22, 0x01); // lead HP, big endian
+        mmu.wb(0xDA2A + 0x23, 0x02);
+        mmu.wb(0xDA2A + 48, 19); // second species
+        mmu.wb(0xD0FF, 0x01); // enemy HP, big endian
+        mmu.wb(0xD100, 0x2C);
+        for (i, &b) in [0x01, 0x23, 0x45].iter().enumerate() {
+            mmu.wb(0xD573 + i as u16, b); // money
+        }
+        mmu.wb(0xD57C, 0x81); // badges
+        mmu.wb(0xFFD3, 0x99); // RNG
+
+        // Another WRAM bank must not be mirrored
+        mmu.wb(0xFF70, 2);
+        mmu.wb(0xDA00, 0xEE);
+        mmu.wb(0xFF70, 1);
+
+        mmu.set_mirror_debug(true);
+        mmu.write_mirror();
+        let mirror = mmu.get_mirror();
+        assert_eq!(mirror[0x004..0x008], [3, 7, 12, 34]);
+        assert_eq!(mirror[0x008..0x00D], [1, 155, 5, 0x02, 0x01]);
+        assert_eq!(mirror[0x014], 19);
+        assert_eq!(mirror[0x04C..0x04E], 300u16.to_le_bytes());
+        assert_eq!(mirror[0x050..0x054], 12345u32.to_le_bytes());
+        assert_eq!(mirror[0x054], 0x81);
+        assert_eq!(mirror[0x058], 0x99);
+    }
+
+    #[test]
+    fn mirror_debug_region() {
+        let mut mmu = mmu(false);
+        mmu.write_mirror();
+        assert_eq!(mmu.get_mirror().len(), super::MIRROR_VISIBLE_SIZE);
+        mmu.set_mirror_debug(true);
+        assert_eq!(mmu.get_mirror().len(), super::MIRROR_SIZE);
+    }
+
+    #[test]
+    fn mirror_wram_base_range() {
+        use crate::mirror::MirrorSpec;
+        let mut mmu = mmu(false);
+        let last = (0xE000 - super::MIRROR_SIZE) as u16;
+        assert!(mmu.set_mirror_wram_base(Some(0x8000)).is_err());
+        assert!(mmu.set_mirror_wram_base(Some(last + 1)).is_err());
+        mmu.set_mirror_wram_base(Some(last)).unwrap();
+        assert!(mmu
+            .set_mirror_spec(MirrorSpec::new(super::MIRROR_SIZE + 1, vec![]))
+            .is_err());
+
+        mmu.set_mirror_spec(MirrorSpec::new(8, vec![])).unwrap();
+        mmu.add_watchpoint(last);
+        mmu.write_mirror();
+        assert_eq!(mmu.rb(last), 1);
+        assert_eq!(mmu.take_watchpoint_hit(), None);
+    }
+
+    #[test]
+    fn custom_mirror_spec() {
+        use crate::mirror::{FieldEncoding, MirrorField, MirrorSpec};
+        let mut mmu = mmu(false);
+        let spec = MirrorSpec::new(
+            12,
+            vec![
+                MirrorField::new(4, 0xC010, 1, FieldEncoding::LittleEndian),
+                MirrorField::new(5, 0xC020, 2, FieldEncoding::BigEndian),
+                MirrorField::new(8, 0xFF90, 2, FieldEncoding::Bcd),
+            ],
+        );
+        mmu.set_mirror_spec(spec.clone()).unwrap();
+        assert_eq!(mmu.mirror_spec, spec);
+        mmu.wb(0xC010, 7);
+        mmu.wb(0xC020, 0x12);
+        mmu.wb(0xC021, 0x34);
+        mmu.wb(0xFF90, 0x12);
+        mmu.wb(0xFF91, 0x34);
+        mmu.write_mirror();
+        assert_eq!(
+            mmu.get_mirror(),
+            [1, 0, 0, 0, 7, 0x34, 0x12, 0, 0xD2, 0x04, 0, 0]
+        );
+
+        let mut large = spec;
+        large.fields[0].offset = 12;
+        assert!(mmu.set_mirror_spec(large).is_err());
     }
 }
@@ -15,6 +15,32 @@ pub struct Registers {
     pub sp: u16,
 }
 
+/// A snapshot of the CPU registers, with both the register pairs and their 8-bit halves,
+/// the flags of F and the interrupt and halt state.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct CpuRegs {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub zf: bool,
+    pub nf: bool,
+    pub hf: bool,
+    pub cf: bool,
+    pub ime: bool,
+    pub halted: bool,
+}
+
 #[derive(Copy, Clone)]
 pub enum CpuFlag {
     C = 0b00010000,
@@ -120,6 +146,32 @@ impl Registers {
         self.f & mask > 0
     }
 
+    /// The registers as a `CpuRegs`, with `ime` and `halted` left false for the CPU to fill.
+    pub fn dump(&self) -> CpuRegs {
+        CpuRegs {
+            a: self.a,
+            f: self.f & 0xF0,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            af: self.af(),
+            bc: self.bc(),
+            de: self.de(),
+            hl: self.hl(),
+            sp: self.sp,
+            pc: self.pc,
+            zf: self.getflag(CpuFlag::Z),
+            nf: self.getflag(CpuFlag::N),
+            hf: self.getflag(CpuFlag::H),
+            cf: self.getflag(CpuFlag::C),
+            ime: false,
+            halted: false,
+        }
+    }
+
     #[cfg(test)]
     fn setf(&mut self, flags: u8) {
         self.f = flags & 0xF0;
@@ -158,6 +210,35 @@ mod test {
         assert_eq!(reg.hl(), 0x1111);
     }
 
+    #[test]
+    fn dump() {
+        let mut reg = Registers::new(GbMode::Classic);
+        reg.setaf(0x12F0);
+        reg.setbc(0x3456);
+        reg.setde(0x789A);
+        reg.sethl(0xBCDE);
+        reg.sp = 0xFFF0;
+        reg.pc = 0x0150;
+
+        let dump = reg.dump();
+        assert_eq!(
+            (dump.af, dump.bc, dump.de, dump.hl),
+            (0x12F0, 0x3456, 0x789A, 0xBCDE)
+        );
+        assert_eq!((dump.a, dump.f), (0x12, 0xF0));
+        assert_eq!((dump.b, dump.c, dump.d, dump.e), (0x34, 0x56, 0x78, 0x9A));
+        assert_eq!((dump.h, dump.l), (0xBC, 0xDE));
+        assert_eq!((dump.sp, dump.pc), (0xFFF0, 0x0150));
+        assert!(dump.zf && dump.nf && dump.hf && dump.cf);
+
+        reg.setaf(0x1250);
+        let dump = reg.dump();
+        assert_eq!(
+            (dump.zf, dump.nf, dump.hf, dump.cf),
+            (false, true, false, true)
+        );
+    }
+
     #[test]
     fn flags() {
         let mut reg = Registers::new(GbMode::Classic);
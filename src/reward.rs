@@ -0,0 +1,117 @@
+use crate::mmu::{
+    MIRROR_BADGES, MIRROR_ENEMY_HP, MIRROR_ENEMY_SPECIES, MIRROR_IN_BATTLE, MIRROR_MONEY,
+    MIRROR_PARTY_COUNT,
+};
+use serde::{Deserialize, Serialize};
+
+/// Scores a frame from the change in the default mirror layout between the previous and the
+/// new mirror. Each weight multiplies the gain of its field, negative gains included.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct RewardConfig {
+    /// Per unit of money gained.
+    pub money: f32,
+    /// Per badge gained.
+    pub badges: f32,
+    /// Per HP the enemy lost, counted only while the same enemy stays in battle.
+    pub enemy_hp: f32,
+    /// End the episode when the party count drops to zero.
+    pub done_on_empty_party: bool,
+}
+
+impl Default for RewardConfig {
+    fn default() -> RewardConfig {
+        RewardConfig {
+            money: 0.01,
+            badges: 10.0,
+            enemy_hp: 0.1,
+            done_on_empty_party: true,
+        }
+    }
+}
+
+fn byte(mirror: &[u8], offset: usize) -> u8 {
+    mirror.get(offset).copied().unwrap_or(0)
+}
+
+fn u16_at(mirror: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([byte(mirror, offset), byte(mirror, offset + 1)])
+}
+
+fn u32_at(mirror: &[u8], offset: usize) -> u32 {
+    u16_at(mirror, offset) as u32 | (u16_at(mirror, offset + 2) as u32) << 16
+}
+
+impl RewardConfig {
+    /// All weights 0 and never done, the placeholder behaviour.
+    pub fn zero() -> RewardConfig {
+        RewardConfig {
+            money: 0.0,
+            badges: 0.0,
+            enemy_hp: 0.0,
+            done_on_empty_party: false,
+        }
+    }
+
+    /// The reward for going from mirror `prev` to `mirror`, and whether the episode is done.
+    /// Fields past the end of a (custom, shorter) mirror read as 0.
+    pub fn reward(&self, prev: &[u8], mirror: &[u8]) -> (f32, bool) {
+        let money = u32_at(mirror, MIRROR_MONEY) as f32 - u32_at(prev, MIRROR_MONEY) as f32;
+        let badges = byte(mirror, MIRROR_BADGES).count_ones() as f32
+            - byte(prev, MIRROR_BADGES).count_ones() as f32;
+        let same_battle = byte(prev, MIRROR_IN_BATTLE) != 0
+            && byte(mirror, MIRROR_IN_BATTLE) != 0
+            && byte(prev, MIRROR_ENEMY_SPECIES) == byte(mirror, MIRROR_ENEMY_SPECIES);
+        let enemy_hp = if same_battle {
+            u16_at(prev, MIRROR_ENEMY_HP) as f32 - u16_at(mirror, MIRROR_ENEMY_HP) as f32
+        } else {
+            0.0
+        };
+        let reward = self.money * money + self.badges * badges + self.enemy_hp * enemy_hp;
+        let done = self.done_on_empty_party
+            && byte(prev, MIRROR_PARTY_COUNT) != 0
+            && byte(mirror, MIRROR_PARTY_COUNT) == 0;
+        (reward, done)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RewardConfig;
+    use crate::mmu::{
+        MIRROR_BADGES, MIRROR_ENEMY_HP, MIRROR_ENEMY_SPECIES, MIRROR_IN_BATTLE, MIRROR_MONEY,
+        MIRROR_PARTY_COUNT, MIRROR_SIZE,
+    };
+
+    #[test]
+    fn reward_from_deltas() {
+        let config = RewardConfig {
+            money: 1.0,
+            badges: 10.0,
+            enemy_hp: 0.5,
+            done_on_empty_party: true,
+        };
+        let mut prev = vec![0; MIRROR_SIZE];
+        prev[MIRROR_PARTY_COUNT] = 1;
+        prev[MIRROR_IN_BATTLE] = 1;
+        prev[MIRROR_ENEMY_SPECIES] = 19;
+        prev[MIRROR_ENEMY_HP] = 20;
+        assert_eq!(config.reward(&prev, &prev), (0.0, false));
+
+        let mut mirror = prev.clone();
+        mirror[MIRROR_MONEY..MIRROR_MONEY + 4].copy_from_slice(&300u32.to_le_bytes());
+        mirror[MIRROR_BADGES] = 0b11;
+        mirror[MIRROR_ENEMY_HP] = 14;
+        assert_eq!(config.reward(&prev, &mirror), (300.0 + 20.0 + 3.0, false));
+        assert_eq!(config.reward(&mirror, &prev), (-323.0, false));
+
+        // A new enemy is not damage
+        mirror[MIRROR_ENEMY_SPECIES] = 20;
+        assert_eq!(config.reward(&prev, &mirror).0, 320.0);
+
+        mirror[MIRROR_PARTY_COUNT] = 0;
+        assert!(config.reward(&prev, &mirror).1);
+        assert!(!config.reward(&mirror, &mirror).1);
+        assert_eq!(RewardConfig::zero().reward(&prev, &mirror), (0.0, false));
+        assert_eq!(config.reward(&[], &[0; 4]), (0.0, false));
+    }
+}
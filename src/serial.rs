@@ -1,15 +1,153 @@
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 
 pub trait SerialCallback: Send {
     fn call(&mut self, value: u8) -> Option<u8>;
+
+    /// Take `cb` in to forward bytes to as well, for callbacks that fan out like `TeeSerial`.
+    /// Others hand it back.
+    fn try_push(&mut self, cb: Box<dyn SerialCallback>) -> Option<Box<dyn SerialCallback>> {
+        Some(cb)
+    }
+}
+
+/// Forwards every byte to all of its callbacks, in order. The reply of the first callback
+/// that returns one is used as the incoming byte.
+pub struct TeeSerial {
+    callbacks: Vec<Box<dyn SerialCallback>>,
+}
+
+impl TeeSerial {
+    pub fn new(callbacks: Vec<Box<dyn SerialCallback>>) -> TeeSerial {
+        TeeSerial { callbacks }
+    }
+
+    pub fn push(&mut self, cb: Box<dyn SerialCallback>) {
+        self.callbacks.push(cb);
+    }
+}
+
+impl SerialCallback for TeeSerial {
+    fn call(&mut self, value: u8) -> Option<u8> {
+        let mut reply = None;
+        for cb in self.callbacks.iter_mut() {
+            let result = cb.call(value);
+            if reply.is_none() {
+                reply = result;
+            }
+        }
+        reply
+    }
+
+    fn try_push(&mut self, cb: Box<dyn SerialCallback>) -> Option<Box<dyn SerialCallback>> {
+        self.push(cb);
+        None
+    }
+}
+
+/// Collects every byte sent into a shared buffer, e.g. for test ROMs that report over serial.
+pub struct BufferedSerial {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BufferedSerial {
+    pub fn new() -> BufferedSerial {
+        BufferedSerial {
+            buffer: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The bytes received so far, shared with the callback.
+    pub fn buffer(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.buffer.clone()
+    }
+}
+
+impl Default for BufferedSerial {
+    fn default() -> BufferedSerial {
+        BufferedSerial::new()
+    }
+}
+
+impl SerialCallback for BufferedSerial {
+    fn call(&mut self, value: u8) -> Option<u8> {
+        self.buffer.lock().unwrap().push(value);
+        None
+    }
+}
+
+/// One Game Boy's end of the shared link cable state.
+#[derive(Default)]
+struct LinkEnd {
+    /// SB, which the other side shifts in when it drives the clock.
+    data: u8,
+    /// Waiting for the other side to clock a transfer (SC = 0x80).
+    waiting: bool,
+    /// A byte clocked in by the other side, not yet picked up.
+    incoming: Option<u8>,
+}
+
+/// One plug of a link cable, see `LinkPort::pair` and `Device::link`.
+pub struct LinkPort {
+    ends: Arc<Mutex<[LinkEnd; 2]>>,
+    side: usize,
+}
+
+impl LinkPort {
+    /// Both plugs of a new link cable.
+    pub fn pair() -> (LinkPort, LinkPort) {
+        let ends = Arc::new(Mutex::new([LinkEnd::default(), LinkEnd::default()]));
+        let first = LinkPort {
+            ends: ends.clone(),
+            side: 0,
+        };
+        let second = LinkPort { ends, side: 1 };
+        (first, second)
+    }
+
+    fn set_data(&self, value: u8) {
+        self.ends.lock().unwrap()[self.side].data = value;
+    }
+
+    fn set_waiting(&self, waiting: bool) {
+        self.ends.lock().unwrap()[self.side].waiting = waiting;
+    }
+
+    /// Clock `value` out with the internal clock. Returns the byte shifted in, 0xFF if the
+    /// other side is not waiting for a transfer.
+    fn send(&self, value: u8) -> u8 {
+        let mut ends = self.ends.lock().unwrap();
+        let other = &mut ends[1 - self.side];
+        if !other.waiting {
+            return 0xFF;
+        }
+        other.waiting = false;
+        other.incoming = Some(value);
+        other.data
+    }
+
+    fn take_incoming(&self) -> Option<u8> {
+        self.ends.lock().unwrap()[self.side].incoming.take()
+    }
 }
 
+/// CPU cycles needed to shift out one byte with the internal clock (8192 Hz).
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
 #[derive(Serialize, Deserialize)]
 pub struct Serial {
     data: u8,
     control: u8,
+    /// Cycles left until the transfer in progress completes, 0 if there is none.
+    #[serde(default)]
+    transfer_cycles: u32,
+    /// The byte shifted in by the transfer in progress.
+    #[serde(default)]
+    incoming: u8,
     #[serde(skip)]
     callback: Option<Box<dyn SerialCallback>>,
+    #[serde(skip)]
+    link: Option<LinkPort>,
     pub interrupt: u8,
 }
 
@@ -18,29 +156,67 @@ impl Serial {
         Serial {
             data: 0,
             control: 0,
+            transfer_cycles: 0,
+            incoming: 0,
             callback: Some(cb),
+            link: None,
             interrupt: 0,
         }
     }
 
     pub fn wb(&mut self, a: u16, v: u8) {
         match a {
-            0xFF01 => self.data = v,
+            0xFF01 => {
+                self.data = v;
+                if let Some(link) = &self.link {
+                    link.set_data(v);
+                }
+            }
             0xFF02 => {
                 self.control = v;
-                if v & 0x81 == 0x81 {
-                    if let Some(callback) = &mut self.callback {
-                        if let Some(result) = callback.call(self.data) {
-                            self.data = result;
-                            self.interrupt = 0x8;
-                        }
+                if let Some(link) = &self.link {
+                    link.set_waiting(v & 0x81 == 0x80);
+                    if v & 0x81 == 0x81 {
+                        self.incoming = link.send(self.data);
+                        self.transfer_cycles = TRANSFER_CYCLES;
                     }
+                } else if v & 0x81 == 0x81 {
+                    // With nothing on the other end the line stays high and 0xFF shifts in
+                    let reply = match &mut self.callback {
+                        Some(callback) => callback.call(self.data),
+                        None => None,
+                    };
+                    self.incoming = reply.unwrap_or(0xFF);
+                    self.transfer_cycles = TRANSFER_CYCLES;
                 }
             }
             _ => panic!("Serial does not handle address {:4X} (write)", a),
         };
     }
 
+    /// Advance a transfer in progress. Once all bits are shifted, the incoming byte lands in
+    /// SB, SC bit 7 clears and the serial interrupt is raised.
+    /// With the external clock, a transfer starts once the linked Game Boy clocks one.
+    pub fn do_cycle(&mut self, ticks: u32) {
+        if self.transfer_cycles == 0 {
+            if let Some(incoming) = self.link.as_ref().and_then(LinkPort::take_incoming) {
+                self.incoming = incoming;
+                self.transfer_cycles = TRANSFER_CYCLES;
+            }
+        }
+        if self.transfer_cycles == 0 {
+            return;
+        }
+        if ticks < self.transfer_cycles {
+            self.transfer_cycles -= ticks;
+            return;
+        }
+        self.transfer_cycles = 0;
+        self.data = self.incoming;
+        self.control &= 0x7F;
+        self.interrupt = 0x8;
+    }
+
     pub fn rb(&self, a: u16) -> u8 {
         match a {
             0xFF01 => self.data,
@@ -53,9 +229,58 @@ impl Serial {
         self.callback = Some(cb);
     }
 
+    /// Install `cb` next to the current callback, if any, instead of replacing it.
+    pub fn add_callback(&mut self, cb: Box<dyn SerialCallback>) {
+        self.callback = match self.callback.take() {
+            None => Some(cb),
+            Some(mut old) => match old.try_push(cb) {
+                None => Some(old),
+                Some(cb) => Some(Box::new(TeeSerial::new(vec![old, cb]))),
+            },
+        };
+    }
+
     pub fn unset_callback(&mut self) {
         self.callback = None;
     }
+
+    /// Remove and return the installed callback, e.g. to move it to another Serial.
+    pub fn take_callback(&mut self) -> Option<Box<dyn SerialCallback>> {
+        self.callback.take()
+    }
+
+    /// Clear the registers and any transfer in progress, keeping the callback and link cable.
+    pub fn reset(&mut self) {
+        self.data = 0;
+        self.control = 0;
+        self.transfer_cycles = 0;
+        self.incoming = 0;
+        self.interrupt = 0;
+        if let Some(link) = &self.link {
+            link.set_data(0);
+            link.set_waiting(false);
+            link.take_incoming();
+        }
+    }
+
+    /// Plug in a link cable, unplugging any previous one. While connected, transfers go over
+    /// the cable instead of to the callback.
+    pub fn connect(&mut self, link: LinkPort) {
+        self.disconnect();
+        link.set_data(self.data);
+        link.set_waiting(self.control & 0x81 == 0x80);
+        self.link = Some(link);
+    }
+
+    /// Unplug the link cable, returning it. This end then looks unplugged to the other side:
+    /// not waiting, with the line pulled high.
+    pub fn disconnect(&mut self) -> Option<LinkPort> {
+        let link = self.link.take()?;
+        link.set_waiting(false);
+        link.set_data(0xFF);
+        link.take_incoming();
+        Some(link)
+    }
 }
 
 impl Serial {
@@ -63,8 +288,168 @@ impl Serial {
         Serial {
             data: 0,
             control: 0,
+            transfer_cycles: 0,
+            incoming: 0,
             callback: None,
+            link: None,
             interrupt: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{BufferedSerial, LinkPort, Serial, SerialCallback, TRANSFER_CYCLES};
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder {
+        bytes: Arc<Mutex<Vec<u8>>>,
+        reply: Option<u8>,
+    }
+
+    impl SerialCallback for Recorder {
+        fn call(&mut self, v: u8) -> Option<u8> {
+            self.bytes.lock().unwrap().push(v);
+            self.reply
+        }
+    }
+
+    fn recorder(reply: Option<u8>) -> (Box<Recorder>, Arc<Mutex<Vec<u8>>>) {
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let rec = Recorder {
+            bytes: bytes.clone(),
+            reply,
+        };
+        (Box::new(rec), bytes)
+    }
+
+    #[test]
+    fn tee_forwards_to_all_callbacks() {
+        let (first, first_bytes) = recorder(None);
+        let (second, second_bytes) = recorder(Some(0x42));
+        let (third, third_bytes) = recorder(Some(0x24));
+        let mut serial = Serial::new();
+        serial.add_callback(first);
+        serial.add_callback(second);
+        serial.add_callback(third);
+
+        for &b in b"hi" {
+            serial.wb(0xFF01, b);
+            serial.wb(0xFF02, 0x81);
+            serial.do_cycle(TRANSFER_CYCLES);
+        }
+
+        assert_eq!(&*first_bytes.lock().unwrap(), b"hi");
+        assert_eq!(&*second_bytes.lock().unwrap(), b"hi");
+        assert_eq!(&*third_bytes.lock().unwrap(), b"hi");
+        assert_eq!(serial.rb(0xFF01), 0x42);
+    }
+
+    #[test]
+    fn buffered_serial_collects_bytes() {
+        let buffered = BufferedSerial::new();
+        let buffer = buffered.buffer();
+        let mut serial = Serial::new_with_callback(Box::new(buffered));
+        for &b in b"ok" {
+            serial.wb(0xFF01, b);
+            serial.wb(0xFF02, 0x81);
+        }
+        assert_eq!(&*buffer.lock().unwrap(), b"ok");
+    }
+
+    #[test]
+    fn control_busy_during_transfer() {
+        let (rec, _) = recorder(Some(0x42));
+        let mut serial = Serial::new_with_callback(rec);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0);
+
+        serial.wb(0xFF01, 0x12);
+        serial.wb(0xFF02, 0x81);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0x80);
+        serial.do_cycle(TRANSFER_CYCLES - 1);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0x80);
+        assert_eq!(serial.rb(0xFF01), 0x12);
+        assert_eq!(serial.interrupt, 0);
+
+        serial.do_cycle(1);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0);
+        assert_eq!(serial.rb(0xFF01), 0x42);
+        assert_eq!(serial.interrupt, 0x8);
+    }
+
+    #[test]
+    fn unconnected_transfer_completes() {
+        let mut serial = Serial::new();
+        serial.wb(0xFF01, 0x12);
+        serial.wb(0xFF02, 0x81);
+        serial.do_cycle(TRANSFER_CYCLES - 4);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0x80);
+        assert_eq!(serial.interrupt, 0);
+
+        serial.do_cycle(4);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0);
+        assert_eq!(serial.rb(0xFF01), 0xFF);
+        assert_eq!(serial.interrupt, 0x8);
+
+        // The external clock never ticks without a partner
+        serial.interrupt = 0;
+        serial.wb(0xFF02, 0x80);
+        serial.do_cycle(10 * TRANSFER_CYCLES);
+        assert_eq!(serial.rb(0xFF02) & 0x80, 0x80);
+        assert_eq!(serial.interrupt, 0);
+    }
+
+    #[test]
+    fn link_exchanges_bytes() {
+        let (a, b) = LinkPort::pair();
+        let mut master = Serial::new();
+        let mut slave = Serial::new();
+        master.connect(a);
+        slave.connect(b);
+
+        // Nobody is waiting on the other end yet
+        master.wb(0xFF01, 0x11);
+        master.wb(0xFF02, 0x81);
+        master.do_cycle(TRANSFER_CYCLES);
+        assert_eq!(master.rb(0xFF01), 0xFF);
+
+        slave.wb(0xFF01, 0x5A);
+        slave.wb(0xFF02, 0x80);
+        slave.do_cycle(TRANSFER_CYCLES);
+        assert_eq!(slave.rb(0xFF02) & 0x80, 0x80);
+        assert_eq!(slave.interrupt, 0);
+
+        master.wb(0xFF01, 0xA5);
+        master.wb(0xFF02, 0x81);
+        master.do_cycle(TRANSFER_CYCLES);
+        slave.do_cycle(TRANSFER_CYCLES - 1);
+        assert_eq!(slave.rb(0xFF02) & 0x80, 0x80);
+        slave.do_cycle(1);
+
+        assert_eq!(master.rb(0xFF01), 0x5A);
+        assert_eq!(master.interrupt, 0x8);
+        assert_eq!(slave.rb(0xFF01), 0xA5);
+        assert_eq!(slave.rb(0xFF02) & 0x80, 0);
+        assert_eq!(slave.interrupt, 0x8);
+
+        // A slave that unplugs while waiting is gone for the master
+        slave.wb(0xFF01, 0x3C);
+        slave.wb(0xFF02, 0x80);
+        slave.disconnect();
+        master.wb(0xFF01, 0x99);
+        master.wb(0xFF02, 0x81);
+        master.do_cycle(TRANSFER_CYCLES);
+        assert_eq!(master.rb(0xFF01), 0xFF);
+
+        // So is one that plugs in another cable while waiting
+        let (a, b) = LinkPort::pair();
+        master.connect(a);
+        slave.connect(b);
+        slave.wb(0xFF02, 0x80);
+        slave.connect(LinkPort::pair().0);
+        master.wb(0xFF01, 0x77);
+        master.wb(0xFF02, 0x81);
+        master.do_cycle(TRANSFER_CYCLES);
+        assert_eq!(master.rb(0xFF01), 0xFF);
+    }
+}
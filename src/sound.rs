@@ -1,4 +1,5 @@
 use blip_buf::BlipBuf;
+use std::collections::VecDeque;
 
 const WAVE_PATTERN: [[i32; 8]; 4] = [
     [-1, -1, -1, -1, 1, -1, -1, -1],
@@ -19,6 +20,38 @@ pub trait AudioPlayer: Send {
     fn play(&mut self, left_channel: &[f32], right_channel: &[f32]);
     fn samples_rate(&self) -> u32;
     fn underflowed(&self) -> bool;
+    /// How many more stereo samples the player takes right now. The samples it cannot take
+    /// wait in the `Sound` buffer, see `Sound::set_max_buffer`.
+    fn free_space(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// An `AudioPlayer` that discards everything, for running the APU without an audio backend.
+pub struct NullAudioPlayer;
+
+impl AudioPlayer for NullAudioPlayer {
+    fn play(&mut self, _left_channel: &[f32], _right_channel: &[f32]) {}
+
+    fn samples_rate(&self) -> u32 {
+        44100
+    }
+
+    fn underflowed(&self) -> bool {
+        false
+    }
+}
+
+/// Output settings for `Sound`. The defaults take the rate from `AudioPlayer::samples_rate`
+/// and hand the player chunks of about 2000 samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SoundConfig {
+    /// Output sample rate in Hz, overriding the rate the player reports. A rate of 0 is
+    /// raised to 1.
+    pub sample_rate: Option<u32>,
+    /// Stereo samples mixed per `AudioPlayer::play` call, give or take one, at most 2000.
+    /// Smaller chunks lower the latency at the cost of more calls.
+    pub buffer_samples: Option<usize>,
 }
 
 struct VolumeEnvelope {
@@ -300,6 +333,23 @@ impl SquareChannel {
         self.active &= self.length.is_active();
     }
 
+    /// Current 4 bit digital output, as reported by the PCM registers
+    fn pcm_amplitude(&self) -> u8 {
+        if !self.active || WAVE_PATTERN[self.duty as usize][self.phase as usize] < 0 {
+            0
+        } else {
+            self.volume_envelope.volume
+        }
+    }
+
+    fn frequency_hz(&self) -> f32 {
+        if !self.active || self.period == 0 {
+            0.0
+        } else {
+            CLOCKS_PER_SECOND as f32 / (self.period * 8) as f32
+        }
+    }
+
     fn sweep_calculate_frequency(&mut self) -> u16 {
         let offset = self.sweep_frequency >> self.sweep_shift;
 
@@ -514,6 +564,27 @@ impl WaveChannel {
         self.active &= self.length.is_active();
     }
 
+    fn pcm_amplitude(&self) -> u8 {
+        if !self.active || self.volume_shift == 0 {
+            return 0;
+        }
+        let wavebyte = self.waveram[self.current_wave as usize >> 1];
+        let sample = if self.current_wave & 1 == 0 {
+            wavebyte >> 4
+        } else {
+            wavebyte & 0xF
+        };
+        sample >> (self.volume_shift - 1)
+    }
+
+    fn frequency_hz(&self) -> f32 {
+        if !self.active || self.period == 0 {
+            0.0
+        } else {
+            CLOCKS_PER_SECOND as f32 / (self.period * 32) as f32
+        }
+    }
+
     fn dmg_maybe_corrupt_waveram(&mut self) {
         // Corrupt when in dmg_mode, the channel is active, and
         // when delay == 0. Delay == 0 means that the next cycle will
@@ -654,6 +725,22 @@ impl NoiseChannel {
         self.length.step();
         self.active &= self.length.is_active();
     }
+
+    fn pcm_amplitude(&self) -> u8 {
+        if !self.active || (self.state >> self.shift_width) & 1 == 0 {
+            0
+        } else {
+            self.volume_envelope.volume
+        }
+    }
+
+    fn frequency_hz(&self) -> f32 {
+        if !self.active || self.period == 0 {
+            0.0
+        } else {
+            CLOCKS_PER_SECOND as f32 / self.period as f32
+        }
+    }
 }
 
 pub struct Sound {
@@ -673,30 +760,75 @@ pub struct Sound {
     reg_ff25: u8,
     need_sync: bool,
     dmg_mode: bool,
+    config: SoundConfig,
+    sample_rate: u32,
     player: Box<dyn AudioPlayer>,
+    /// Mixed samples the player had no space for yet, oldest first.
+    buffer: VecDeque<(f32, f32)>,
+    max_buffer: usize,
+    overruns: u64,
 }
 
 impl Sound {
-    pub fn new_dmg(player: Box<dyn AudioPlayer>) -> Sound {
-        Sound::new_internal(player, true)
+    pub fn new_dmg(player: Box<dyn AudioPlayer>, config: SoundConfig) -> Sound {
+        Sound::new_internal(player, true, config)
     }
 
-    pub fn new_cgb(player: Box<dyn AudioPlayer>) -> Sound {
-        Sound::new_internal(player, false)
+    pub fn new_cgb(player: Box<dyn AudioPlayer>, config: SoundConfig) -> Sound {
+        Sound::new_internal(player, false, config)
     }
 
     pub fn set_on(&mut self) {
         self.on = true;
     }
 
-    fn new_internal(player: Box<dyn AudioPlayer>, dmg_mode: bool) -> Sound {
-        let blipbuf1 = create_blipbuf(player.samples_rate());
-        let blipbuf2 = create_blipbuf(player.samples_rate());
-        let blipbuf3 = create_blipbuf(player.samples_rate());
-        let blipbuf4 = create_blipbuf(player.samples_rate());
+    /// A freshly created APU with the same output, config, power state and buffer limit.
+    pub fn reset(self) -> Sound {
+        let mut sound = Sound::new_internal(self.player, self.dmg_mode, self.config);
+        sound.on = self.on;
+        sound.max_buffer = self.max_buffer;
+        sound
+    }
+
+    /// Keep at most `samples` stereo samples waiting for the player, dropping the oldest ones
+    /// beyond that. The default is one second.
+    pub fn set_max_buffer(&mut self, samples: usize) {
+        self.max_buffer = samples;
+        self.trim_buffer();
+    }
+
+    /// Number of stereo samples waiting for the player.
+    #[cfg(test)]
+    pub fn buffered_samples(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of stereo samples dropped because the buffer was full.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    /// Output sample rate in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn new_internal(player: Box<dyn AudioPlayer>, dmg_mode: bool, config: SoundConfig) -> Sound {
+        let sample_rate = config
+            .sample_rate
+            .unwrap_or_else(|| player.samples_rate())
+            .max(1);
+        let sample_count = config
+            .buffer_samples
+            .unwrap_or(OUTPUT_SAMPLE_COUNT)
+            .clamp(1, OUTPUT_SAMPLE_COUNT);
 
-        let output_period =
-            (OUTPUT_SAMPLE_COUNT as u64 * CLOCKS_PER_SECOND as u64) / player.samples_rate() as u64;
+        let blipbuf1 = create_blipbuf(sample_rate, sample_count);
+        let blipbuf2 = create_blipbuf(sample_rate, sample_count);
+        let blipbuf3 = create_blipbuf(sample_rate, sample_count);
+        let blipbuf4 = create_blipbuf(sample_rate, sample_count);
+
+        let output_period = (sample_count as u64 * CLOCKS_PER_SECOND as u64) / sample_rate as u64;
 
         Sound {
             on: false,
@@ -715,7 +847,12 @@ impl Sound {
             reg_ff25: 0x00,
             need_sync: false,
             dmg_mode: dmg_mode,
+            config,
+            sample_rate,
+            max_buffer: sample_rate as usize,
             player: player,
+            buffer: VecDeque::new(),
+            overruns: 0,
         }
     }
 
@@ -737,11 +874,44 @@ impl Sound {
                     | if self.channel1.on() { 0x1 } else { 0x0 })
             }
             0xFF30..=0xFF3F => self.channel3.rb(a),
+            0xFF76 => (self.channel2.pcm_amplitude() << 4) | self.channel1.pcm_amplitude(),
+            0xFF77 => (self.channel4.pcm_amplitude() << 4) | self.channel3.pcm_amplitude(),
             _ => 0xFF,
         };
         return v;
     }
 
+    /// The frame sequencer step (0-7) that is executed next. Length counters are clocked on
+    /// the even steps, the sweep on steps 2 and 6 and the volume envelopes on step 7.
+    pub fn frame_sequencer_step(&self) -> u8 {
+        let pending = if self.time >= self.next_time {
+            (self.time - self.next_time) / CLOCKS_PER_FRAME + 1
+        } else {
+            0
+        };
+        ((self.frame_step as u32 + pending) % 8) as u8
+    }
+
+    /// Current output frequency in Hz of each of the four channels, 0 for silent channels.
+    pub fn channel_frequencies(&self) -> [f32; 4] {
+        [
+            self.channel1.frequency_hz(),
+            self.channel2.frequency_hz(),
+            self.channel3.frequency_hz(),
+            self.channel4.frequency_hz(),
+        ]
+    }
+
+    /// Current 4 bit digital output of each of the four channels.
+    pub fn channel_amplitudes(&self) -> [u8; 4] {
+        [
+            self.channel1.pcm_amplitude(),
+            self.channel2.pcm_amplitude(),
+            self.channel3.pcm_amplitude(),
+            self.channel4.pcm_amplitude(),
+        ]
+    }
+
     pub fn wb(&mut self, a: u16, v: u8) {
         if !self.on {
             // Allow writes to the length register when in DMG mode
@@ -926,12 +1096,36 @@ impl Sound {
             debug_assert!(count1 == count3);
             debug_assert!(count1 == count4);
 
-            self.player.play(&buf_left[..count1], &buf_right[..count1]);
+            self.output(&buf_left[..count1], &buf_right[..count1]);
 
             outputted += count1;
         }
     }
 
+    /// Hand mixed samples to the player, buffering what it has no space for.
+    fn output(&mut self, left: &[f32], right: &[f32]) {
+        if self.buffer.is_empty() && self.player.free_space() >= left.len() {
+            self.player.play(left, right);
+            return;
+        }
+        self.buffer
+            .extend(left.iter().copied().zip(right.iter().copied()));
+        self.trim_buffer();
+        let count = self.player.free_space().min(self.buffer.len());
+        if count > 0 {
+            let (left, right): (Vec<f32>, Vec<f32>) = self.buffer.drain(..count).unzip();
+            self.player.play(&left, &right);
+        }
+    }
+
+    fn trim_buffer(&mut self) {
+        let excess = self.buffer.len().saturating_sub(self.max_buffer);
+        if excess > 0 {
+            self.buffer.drain(..excess);
+            self.overruns += excess as u64;
+        }
+    }
+
     fn clear_buffers(&mut self) {
         self.channel1.blip.clear();
         self.channel2.blip.clear();
@@ -940,10 +1134,129 @@ impl Sound {
     }
 }
 
-fn create_blipbuf(samples_rate: u32) -> BlipBuf {
-    // Create a BlipBuf which can hold OUTPUT_SAMPLE_COUNT + 1 samples.
+fn create_blipbuf(samples_rate: u32, sample_count: usize) -> BlipBuf {
+    // Create a BlipBuf which can hold sample_count + 1 samples.
     // Not sure why the +1 is needed. May need to correct the constant instead.
-    let mut blipbuf = BlipBuf::new((OUTPUT_SAMPLE_COUNT + 1) as u32);
+    let mut blipbuf = BlipBuf::new((sample_count + 1) as u32);
     blipbuf.set_rates(CLOCKS_PER_SECOND as f64, samples_rate as f64);
     blipbuf
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        AudioPlayer, NullAudioPlayer, Sound, SoundConfig, CLOCKS_PER_FRAME, CLOCKS_PER_SECOND,
+    };
+
+    /// A player nothing ever reads from.
+    struct StuckPlayer;
+
+    impl AudioPlayer for StuckPlayer {
+        fn play(&mut self, _left_channel: &[f32], _right_channel: &[f32]) {
+            panic!("StuckPlayer has no space");
+        }
+        fn samples_rate(&self) -> u32 {
+            44100
+        }
+        fn underflowed(&self) -> bool {
+            false
+        }
+        fn free_space(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn buffer_limit() {
+        let mut sound = Sound::new_dmg(Box::new(StuckPlayer), SoundConfig::default());
+        sound.set_on();
+        sound.set_max_buffer(100);
+        // One second
+        for _ in 0..CLOCKS_PER_SECOND / 64 {
+            sound.do_cycle(64);
+        }
+        assert_eq!(sound.buffered_samples(), 100);
+        assert!(sound.overruns() > 40_000);
+
+        sound.set_max_buffer(10);
+        assert_eq!(sound.buffered_samples(), 10);
+        let overruns = sound.overruns();
+        let sound = sound.reset();
+        assert_eq!(sound.overruns(), 0);
+        assert_eq!(sound.max_buffer, 10);
+        assert!(overruns > 0);
+    }
+
+    #[test]
+    fn frame_sequencer_cycles() {
+        let mut sound = Sound::new_dmg(Box::new(NullAudioPlayer), SoundConfig::default());
+        sound.set_on();
+        let mut steps = Vec::new();
+        for _ in 0..10 {
+            steps.push(sound.frame_sequencer_step());
+            sound.do_cycle(CLOCKS_PER_FRAME);
+        }
+        assert_eq!(steps, vec![0, 1, 2, 3, 4, 5, 6, 7, 0, 1]);
+    }
+
+    /// Counts the samples handed to the player.
+    struct CountingPlayer(std::sync::Arc<std::sync::Mutex<Vec<usize>>>);
+
+    impl AudioPlayer for CountingPlayer {
+        fn play(&mut self, left_channel: &[f32], _right_channel: &[f32]) {
+            self.0.lock().unwrap().push(left_channel.len());
+        }
+        fn samples_rate(&self) -> u32 {
+            44100
+        }
+        fn underflowed(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn configured_sample_rate() {
+        for &rate in &[44100, 48000] {
+            let chunks = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let config = SoundConfig {
+                sample_rate: Some(rate),
+                buffer_samples: Some(500),
+            };
+            let mut sound = Sound::new_cgb(Box::new(CountingPlayer(chunks.clone())), config);
+            sound.set_on();
+            assert_eq!(sound.sample_rate(), rate);
+            // One second
+            for _ in 0..CLOCKS_PER_SECOND / 64 {
+                sound.do_cycle(64);
+            }
+            let chunks = chunks.lock().unwrap();
+            assert!(chunks.iter().all(|&n| n <= 501));
+            let total: usize = chunks.iter().sum();
+            assert!(
+                (total as i64 - rate as i64).abs() <= 500,
+                "{} at {}",
+                total,
+                rate
+            );
+
+            let sound = sound.reset();
+            assert_eq!(sound.sample_rate(), rate);
+        }
+        let sound = Sound::new_dmg(Box::new(NullAudioPlayer), SoundConfig::default());
+        assert_eq!(sound.sample_rate(), 44100);
+    }
+
+    #[test]
+    fn zero_sample_rate_is_clamped() {
+        let config = SoundConfig {
+            sample_rate: Some(0),
+            buffer_samples: None,
+        };
+        let mut sound = Sound::new_dmg(Box::new(NullAudioPlayer), config);
+        assert_eq!(sound.sample_rate(), 1);
+        sound.set_on();
+        for _ in 0..CLOCKS_PER_FRAME / 64 {
+            sound.do_cycle(64);
+        }
+    }
+}
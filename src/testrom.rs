@@ -0,0 +1,38 @@
+//! Builders for small synthetic cartridges used by the unit tests.
+
+pub const ROM_SIZE: usize = 0x8000;
+
+/// An MBC-less 32KiB cartridge with `code` placed at the 0x0100 entry point.
+pub fn rom(code: &[u8]) -> Vec<u8> {
+    cartridge(0x00, 0x00, 0x00, code)
+}
+
+/// A cartridge with the given header type, ROM size and RAM size bytes. The image is sized
+/// to match the ROM-size byte.
+pub fn cartridge(cart_type: u8, rom_size: u8, ram_size: u8, code: &[u8]) -> Vec<u8> {
+    let mut data = vec![0; ROM_SIZE << rom_size];
+    data[0x100..0x100 + code.len()].copy_from_slice(code);
+    data[0x147] = cart_type;
+    data[0x148] = rom_size;
+    data[0x149] = ram_size;
+    fix_checksum(&mut data);
+    data
+}
+
+/// An endless `JR -2` loop, leaving the LCD and timers running.
+pub fn idle_rom() -> Vec<u8> {
+    rom(&[0x18, 0xFE])
+}
+
+pub fn set_title(data: &mut [u8], title: &[u8]) {
+    data[0x134..0x134 + title.len()].copy_from_slice(title);
+    fix_checksum(data);
+}
+
+pub fn fix_checksum(data: &mut [u8]) {
+    let mut value: u8 = 0;
+    for v in data[0x134..0x14D].iter() {
+        value = value.wrapping_sub(*v).wrapping_sub(1);
+    }
+    data[0x14D] = value;
+}
@@ -1,34 +1,38 @@
 use serde::{Deserialize, Serialize};
 
+/// DIV and TIMA both run off a 16-bit counter that goes up every CPU cycle. DIV (0xFF04) is
+/// its upper byte, and writing DIV clears the whole counter. TIMA goes up on every falling edge
+/// of the counter bit that TAC selects (bit 9, 3, 5 or 7), ANDed with the TAC enable bit. So
+/// clearing the counter, disabling the timer or switching to another frequency while that
+/// signal is high gives an extra TIMA increment, like on hardware.
 #[derive(Serialize, Deserialize)]
 pub struct Timer {
-    divider: u8,
+    /// The internal counter.
+    #[serde(default)]
+    div: u16,
     counter: u8,
     modulo: u8,
     enabled: bool,
+    /// Cycles per TIMA increment, twice the value of the selected counter bit.
     step: u32,
-    internalcnt: u32,
-    internaldiv: u32,
     pub interrupt: u8,
 }
 
 impl Timer {
     pub fn new() -> Timer {
         Timer {
-            divider: 0,
+            div: 0,
             counter: 0,
             modulo: 0,
             enabled: false,
             step: 1024,
-            internalcnt: 0,
-            internaldiv: 0,
             interrupt: 0,
         }
     }
 
     pub fn rb(&self, a: u16) -> u8 {
         match a {
-            0xFF04 => self.divider,
+            0xFF04 => (self.div >> 8) as u8,
             0xFF05 => self.counter,
             0xFF06 => self.modulo,
             0xFF07 => {
@@ -44,10 +48,19 @@ impl Timer {
         }
     }
 
+    /// The full 16-bit internal divider, of which DIV (0xFF04) is the upper byte.
+    pub fn div_counter(&self) -> u16 {
+        self.div
+    }
+
     pub fn wb(&mut self, a: u16, v: u8) {
         match a {
             0xFF04 => {
-                self.divider = 0;
+                let signal = self.signal();
+                self.div = 0;
+                if signal {
+                    self.increment();
+                }
             }
             0xFF05 => {
                 self.counter = v;
@@ -56,6 +69,7 @@ impl Timer {
                 self.modulo = v;
             }
             0xFF07 => {
+                let signal = self.signal();
                 self.enabled = v & 0x4 != 0;
                 self.step = match v & 0x3 {
                     1 => 16,
@@ -63,29 +77,114 @@ impl Timer {
                     3 => 256,
                     _ => 1024,
                 };
+                if signal && !self.signal() {
+                    self.increment();
+                }
             }
             _ => panic!("Timer does not handler write {:4X}", a),
         };
     }
 
     pub fn do_cycle(&mut self, ticks: u32) {
-        self.internaldiv += ticks;
-        while self.internaldiv >= 256 {
-            self.divider = self.divider.wrapping_add(1);
-            self.internaldiv -= 256;
-        }
+        let old = self.div as u32;
+        let new = old + ticks;
+        self.div = new as u16;
 
         if self.enabled {
-            self.internalcnt += ticks;
-
-            while self.internalcnt >= self.step {
-                self.counter = self.counter.wrapping_add(1);
-                if self.counter == 0 {
-                    self.counter = self.modulo;
-                    self.interrupt |= 0x04;
-                }
-                self.internalcnt -= self.step;
+            // The selected bit falls each time the counter reaches a multiple of step
+            for _ in old / self.step..new / self.step {
+                self.increment();
             }
         }
     }
+
+    /// The input of the falling edge detector.
+    fn signal(&self) -> bool {
+        self.enabled && self.div as u32 & (self.step / 2) != 0
+    }
+
+    fn increment(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+        if self.counter == 0 {
+            self.counter = self.modulo;
+            self.interrupt |= 0x04;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Timer;
+
+    #[test]
+    fn div_counter() {
+        let mut timer = Timer::new();
+        timer.do_cycle(1000);
+        assert_eq!(timer.div_counter(), 1000);
+        assert_eq!(timer.rb(0xFF04), (1000 >> 8) as u8);
+        for _ in 0..100 {
+            timer.do_cycle(700);
+        }
+        assert_eq!(timer.div_counter(), ((1000 + 70000) % 0x10000) as u16);
+    }
+
+    #[test]
+    fn div_write_resets_counter() {
+        let mut timer = Timer::new();
+        timer.do_cycle(0x1234);
+        timer.wb(0xFF04, 0xAB);
+        assert_eq!(timer.div_counter(), 0);
+        timer.do_cycle(100);
+        assert_eq!(timer.rb(0xFF04), 0);
+        assert_eq!(timer.div_counter(), 100);
+    }
+
+    #[test]
+    fn tima_rates() {
+        for &(tac, step) in &[(0x4, 1024), (0x5, 16), (0x6, 64), (0x7, 256)] {
+            let mut timer = Timer::new();
+            timer.wb(0xFF07, tac);
+            timer.do_cycle(step * 10 - 1);
+            assert_eq!(timer.rb(0xFF05), 9, "TAC {:02X}", tac);
+            timer.do_cycle(1);
+            assert_eq!(timer.rb(0xFF05), 10, "TAC {:02X}", tac);
+        }
+    }
+
+    #[test]
+    fn tima_overflow_reloads() {
+        let mut timer = Timer::new();
+        timer.wb(0xFF06, 0xF0);
+        timer.wb(0xFF05, 0xFF);
+        timer.wb(0xFF07, 0x5);
+        timer.do_cycle(16);
+        assert_eq!(timer.rb(0xFF05), 0xF0);
+        assert_eq!(timer.interrupt, 0x04);
+    }
+
+    #[test]
+    fn falling_edge_glitches() {
+        // Bit 3 is high halfway through a 16 cycle period
+        let mut timer = Timer::new();
+        timer.wb(0xFF07, 0x5);
+        timer.do_cycle(8);
+        timer.wb(0xFF04, 0);
+        assert_eq!(timer.rb(0xFF05), 1);
+
+        // Bit 3 high, bit 9 low: switching from 16 to 1024 cycles is a falling edge
+        timer.do_cycle(8);
+        timer.wb(0xFF07, 0x4);
+        assert_eq!(timer.rb(0xFF05), 2);
+
+        // Disabling while bit 9 is high as well
+        timer.do_cycle(512);
+        timer.wb(0xFF07, 0x0);
+        assert_eq!(timer.rb(0xFF05), 3);
+
+        // Writing DIV while the timer is off or the bit is low does nothing
+        timer.wb(0xFF04, 0);
+        timer.wb(0xFF07, 0x4);
+        timer.wb(0xFF04, 0);
+        assert_eq!(timer.rb(0xFF05), 3);
+    }
 }